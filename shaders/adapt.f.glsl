@@ -0,0 +1,25 @@
+#version 410
+
+uniform sampler2D log_lum;
+uniform sampler2D previous;
+
+layout(std140) uniform auto_exposure {
+    float min_ev;
+    float max_ev;
+    float ev_compensation;
+    float adapt_speed;
+    float delta_time;
+    int auto_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_adapted;
+
+void main() {
+    // the last level of the log_lum chain is a single texel, the log-average luminance of the whole frame
+    float target = clamp(texelFetch(log_lum, ivec2(0), 0).r, min_ev, max_ev);
+    float prev = texelFetch(previous, ivec2(0), 0).r;
+
+    float t = 1.0 - exp(-delta_time * adapt_speed);
+    f_adapted = vec4(mix(prev, target, t));
+}
@@ -0,0 +1,40 @@
+#version 410
+
+uniform sampler2D source;
+
+layout(std140) uniform bloom {
+    float bloom_threshold;
+    float bloom_knee;
+    float bloom_radius;
+    float bloom_intensity;
+    int bloom_prefilter;
+    int bloom_debug;
+};
+
+in vec2 v_pos;
+out vec4 f_bloom;
+
+// soft-knee threshold, only applied when reading the full resolution luminance
+vec3 prefilter(vec3 c) {
+    float brightness = max(c.r, max(c.g, c.b));
+    float soft = clamp(brightness - bloom_threshold + bloom_knee, 0.0, 2.0 * bloom_knee);
+    soft = soft * soft / (4.0 * bloom_knee + 1e-4);
+    float contrib = max(soft, brightness - bloom_threshold) / max(brightness, 1e-4);
+    return c * contrib;
+}
+
+void main() {
+    // four bilinear taps average a 4x4 block of the source
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+    vec3 c = texture(source, v_pos + texel * vec2(-1, -1)).rgb
+           + texture(source, v_pos + texel * vec2( 1, -1)).rgb
+           + texture(source, v_pos + texel * vec2(-1,  1)).rgb
+           + texture(source, v_pos + texel * vec2( 1,  1)).rgb;
+    c *= 0.25;
+
+    if (bloom_prefilter != 0) {
+        c = prefilter(c);
+    }
+
+    f_bloom = vec4(c, 1);
+}
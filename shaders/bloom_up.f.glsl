@@ -0,0 +1,31 @@
+#version 410
+
+uniform sampler2D source;
+
+layout(std140) uniform bloom {
+    float bloom_threshold;
+    float bloom_knee;
+    float bloom_radius;
+    float bloom_intensity;
+    int bloom_prefilter;
+    int bloom_debug;
+};
+
+in vec2 v_pos;
+out vec4 f_bloom;
+
+void main() {
+    // 3x3 tent filter, added on top of the next larger level
+    vec2 d = bloom_radius / vec2(textureSize(source, 0));
+    vec3 c = texture(source, v_pos).rgb * 4.0;
+    c += texture(source, v_pos + vec2(-d.x, 0)).rgb * 2.0;
+    c += texture(source, v_pos + vec2( d.x, 0)).rgb * 2.0;
+    c += texture(source, v_pos + vec2(0, -d.y)).rgb * 2.0;
+    c += texture(source, v_pos + vec2(0,  d.y)).rgb * 2.0;
+    c += texture(source, v_pos + vec2(-d.x, -d.y)).rgb;
+    c += texture(source, v_pos + vec2( d.x, -d.y)).rgb;
+    c += texture(source, v_pos + vec2(-d.x,  d.y)).rgb;
+    c += texture(source, v_pos + vec2( d.x,  d.y)).rgb;
+
+    f_bloom = vec4(c / 16.0, 1);
+}
@@ -0,0 +1,47 @@
+// Cook-Torrance with a GGX distribution, Smith-Schlick geometry and Schlick's fresnel
+
+const float PI = 3.14159265359;
+
+vec3 fresnelSchlick(float cosTheta, vec3 F0)
+{
+    return F0 + (1.0 - F0) * pow(1.0 - cosTheta, 5.0);
+}
+
+float distributionGGX(vec3 N, vec3 H, float roughness)
+{
+    float a = roughness * roughness;
+    a = a * a;
+    float n_dot_h = max(dot(N, H), 0.0);
+    float n_dot_h2 = n_dot_h * n_dot_h;
+    
+    float denom = (n_dot_h2 * (a - 1.0) + 1.0);
+    denom = PI * denom * denom;
+    
+    return a / denom;
+}
+
+float geometrySchlickGGX(float n_dot_v, float roughness)
+{
+    float rough_more = (roughness + 1.0);
+    float k = (rough_more * rough_more) / 8.0;
+
+    float denom = n_dot_v * (1.0 - k) + k;
+    
+    return n_dot_v / denom;
+}
+
+float geometrySmith(vec3 N, vec3 V, vec3 L, float roughness)
+{
+    float n_dot_v = max(dot(N, V), 0.0);
+    float n_dot_l = max(dot(N, L), 0.0);
+    float ggx2 = geometrySchlickGGX(n_dot_v, roughness);
+    float ggx1 = geometrySchlickGGX(n_dot_l, roughness);
+    
+    return ggx1 * ggx2;
+}
+
+// fresnel for image based light, where the roughness hides the edges
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 F0, float roughness)
+{
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - cosTheta, 5.0);
+}
@@ -5,17 +5,44 @@ in vec3 v_norm;
 in vec2 v_tex;
 in vec3 v_tan;
 in vec3 v_bitan;
+in vec2 v_material; // roughness and metalness multipliers
 
-out vec4 layer_a;
-out vec4 layer_b;
+out vec2 layer_n;
+out vec4 layer_albedo;
+out vec4 layer_material;
+out vec4 layer_v;
 
 uniform sampler2D normal_tex;
+uniform sampler2D albedo_tex;
+uniform sampler2D metalness_tex;
+uniform sampler2D roughness_tex;
+uniform sampler2D ao_tex;
+
+layout(std140) uniform aa {
+    mat4 prev_view_proj;
+    mat4 curr_view_proj;
+    vec4 jitter;
+    float taa_feedback;
+    int fxaa_enabled;
+};
+
+#include "normal.glsl"
 
 void main() {
     vec3 normal_map = texture(normal_tex, v_tex).rgb * 2 - 1;
 
     vec3 norm = mat3(v_tan, v_bitan, v_norm) * normal_map;
 
-    layer_a = vec4(v_pos.xyz, norm.x);
-    layer_b = vec4(norm.yz, v_tex.xy);
-}
\ No newline at end of file
+    layer_n = encode_normal(normalize(norm));
+    layer_albedo = vec4(texture(albedo_tex, v_tex).rgb, 1);
+    layer_material = vec4(
+        texture(roughness_tex, v_tex).r * v_material.x,
+        texture(metalness_tex, v_tex).r * v_material.y,
+        texture(ao_tex, v_tex).r,
+        1);
+
+    // screen space motion since the last frame, without jitter, and the uv for debugging
+    vec4 curr = curr_view_proj * vec4(v_pos, 1);
+    vec4 prev = prev_view_proj * vec4(v_pos, 1);
+    layer_v = vec4((curr.xy / curr.w - prev.xy / prev.w) * 0.5, v_tex);
+}
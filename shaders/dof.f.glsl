@@ -0,0 +1,71 @@
+#version 410
+
+const int DOF_SAMPLES = 48;
+
+uniform sampler2D luminance;
+uniform sampler2D depth_tex;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+layout(std140) uniform dof {
+    vec4 focus_point; // xy = uv to focus on, z = fixed focus distance, w = use the uv
+    float focal_length;
+    float f_stop;
+    float sensor_height;
+    int dof_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_lum;
+
+#include "position.glsl"
+
+// distance along the view axis in millimeters, background counts as very far away
+float depth_mm(vec2 uv) {
+    float d = texture(depth_tex, uv).r;
+    if (d <= 0.0) return 1e6;
+    return -(view * vec4(world_pos(uv, d), 1)).z * 1000.0;
+}
+
+// radius of the thin lens circle of confusion in pixels
+float coc(float depth, float focus) {
+    float aperture = focal_length / f_stop;
+    float c = aperture * focal_length * (depth - focus) / (depth * (focus - focal_length));
+    return abs(c) / sensor_height * float(textureSize(luminance, 0).y) * 0.5;
+}
+
+void main() {
+    vec3 center = texture(luminance, v_pos).rgb;
+
+    if (dof_enabled == 0) {
+        f_lum = vec4(center, 1);
+        return;
+    }
+
+    float focus = focus_point.w > 0.5 ? depth_mm(focus_point.xy) : focus_point.z * 1000.0;
+    float radius = min(coc(depth_mm(v_pos), focus), 24.0);
+
+    vec2 texel = 1.0 / vec2(textureSize(luminance, 0));
+    vec3 total = center;
+    float weight = 1.0;
+
+    // golden angle spiral over the bokeh disk; samples only count if their own blur reaches us
+    for (int i = 1; i < DOF_SAMPLES; i++) {
+        float r = sqrt(float(i) / float(DOF_SAMPLES)) * radius;
+        float theta = float(i) * 2.39996323;
+        vec2 uv = v_pos + vec2(cos(theta), sin(theta)) * r * texel;
+
+        float sample_coc = min(coc(depth_mm(uv), focus), 24.0);
+        float w = smoothstep(r - 1.0, r + 1.0, max(sample_coc, radius));
+
+        total += texture(luminance, uv).rgb * w;
+        weight += w;
+    }
+
+    f_lum = vec4(total / weight, 1);
+}
@@ -0,0 +1,63 @@
+#version 410
+
+uniform sampler2D source;
+
+layout(std140) uniform aa {
+    mat4 prev_view_proj;
+    mat4 curr_view_proj;
+    vec4 jitter;
+    float taa_feedback;
+    int fxaa_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_color;
+
+const float FXAA_SPAN_MAX = 8.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+
+float luma(vec3 c) {
+    return dot(c, vec3(0.299, 0.587, 0.114));
+}
+
+// FXAA in the style of Timothy Lottes' original "lite" version
+vec3 fxaa(vec2 uv) {
+    vec2 texel = 1.0 / vec2(textureSize(source, 0));
+
+    float nw = luma(texture(source, uv + vec2(-1, -1) * texel).rgb);
+    float ne = luma(texture(source, uv + vec2( 1, -1) * texel).rgb);
+    float sw = luma(texture(source, uv + vec2(-1,  1) * texel).rgb);
+    float se = luma(texture(source, uv + vec2( 1,  1) * texel).rgb);
+    vec3 m_rgb = texture(source, uv).rgb;
+    float m = luma(m_rgb);
+
+    float lo = min(m, min(min(nw, ne), min(sw, se)));
+    float hi = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2(
+        -((nw + ne) - (sw + se)),
+         ((nw + sw) - (ne + se)));
+
+    float reduce = max((nw + ne + sw + se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float rcp_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * rcp_min, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 a = 0.5 * (
+        texture(source, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(source, uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 b = a * 0.5 + 0.25 * (
+        texture(source, uv + dir * -0.5).rgb +
+        texture(source, uv + dir * 0.5).rgb);
+
+    float lb = luma(b);
+    return (lb < lo || lb > hi) ? a : b;
+}
+
+void main() {
+    if (fxaa_enabled != 0) {
+        f_color = vec4(fxaa(v_pos), 1);
+    } else {
+        f_color = texture(source, v_pos);
+    }
+}
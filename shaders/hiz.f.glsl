@@ -0,0 +1,45 @@
+#version 410
+
+uniform sampler2D depth_tex;
+uniform sampler2D hiz;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+layout(std140) uniform ssr {
+    float ssr_max_dist;
+    float ssr_thickness;
+    float ssr_intensity;
+    int ssr_enabled;
+    int hiz_level;
+    int hiz_levels;
+};
+
+in vec2 v_pos;
+out vec4 f_depth;
+out vec4 f_pyramid;
+
+// Level 0 is the device depth of every pixel, every other level is the maximum (closest, with reverse-Z) depth
+// of a 2x2 block of the previous level (bound as hiz). Each level goes to its own texture and to the pyramid.
+void main() {
+    if (hiz_level == 0) {
+        f_depth = vec4(texture(depth_tex, v_pos).r);
+        f_pyramid = f_depth;
+        return;
+    }
+
+    ivec2 below = textureSize(hiz, 0) - 1;
+    ivec2 p = ivec2(gl_FragCoord.xy) * 2;
+
+    float d = texelFetch(hiz, min(p, below), 0).r;
+    d = max(d, texelFetch(hiz, min(p + ivec2(1, 0), below), 0).r);
+    d = max(d, texelFetch(hiz, min(p + ivec2(0, 1), below), 0).r);
+    d = max(d, texelFetch(hiz, min(p + ivec2(1, 1), below), 0).r);
+
+    f_depth = vec4(d);
+    f_pyramid = f_depth;
+}
@@ -1,23 +1,284 @@
 #version 410
 
 uniform sampler2D luminance;
+uniform sampler2D adapted;
+#ifndef DEBUG_VIEW
+uniform sampler2D bloom_tex;
+uniform sampler3D lut;
+#endif
 
-layout(std140) uniform live {
-    vec4 eye_pos;
-    float gamma;
-    float exposure;
-    float time;
+#include "live.glsl"
+
+layout(std140) uniform auto_exposure {
+    float min_ev;
+    float max_ev;
+    float ev_compensation;
+    float adapt_speed;
+    float delta_time;
+    int auto_enabled;
+};
+
+#ifndef DEBUG_VIEW
+layout(std140) uniform bloom {
+    float bloom_threshold;
+    float bloom_knee;
+    float bloom_radius;
+    float bloom_intensity;
+    int bloom_prefilter;
+    int bloom_debug;
 };
 
+layout(std140) uniform grade {
+    vec4 white_balance;
+    vec4 grade_lift;
+    vec4 grade_gamma;
+    vec4 grade_gain;
+    vec4 lut_domain_min;
+    vec4 lut_domain_max;
+    float saturation;
+    float lut_size;
+    int lut_enabled;
+};
+#endif
+
+#ifdef DEBUG_VIEW
+uniform sampler2D depth_tex;
+uniform sampler2D layer_n;
+uniform sampler2D layer_albedo;
+uniform sampler2D layer_material;
+uniform sampler2D velocity;
+uniform sampler2D shadow_depth;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+layout(std140) uniform debug {
+    vec4 shadow_clip; // near, far of the shadow projection
+    int debug_view;
+};
+#endif
+
 in vec2 v_pos;
 out vec4 f_color;
 
+const int TONEMAP_EXPONENTIAL = 0;
+const int TONEMAP_REINHARD = 1;
+const int TONEMAP_ACES = 2;
+const int TONEMAP_HABLE = 3;
+const int TONEMAP_AGX = 4;
+
+const float REINHARD_WHITE = 4.0;
+const float HABLE_WHITE = 11.2;
+
+vec3 reinhard_extended(vec3 c) {
+    float l = dot(c, vec3(0.2126, 0.7152, 0.0722));
+    float mapped = l * (1.0 + l / (REINHARD_WHITE * REINHARD_WHITE)) / (1.0 + l);
+    return c * (mapped / max(l, 1e-5));
+}
+
+// Stephen Hill's fit of the ACES RRT + ODT
+vec3 aces_fitted(vec3 c) {
+    const mat3 aces_in = mat3(
+        0.59719, 0.07600, 0.02840,
+        0.35458, 0.90834, 0.13383,
+        0.04823, 0.01566, 0.83777);
+    const mat3 aces_out = mat3(
+         1.60475, -0.10208, -0.00327,
+        -0.53108,  1.10813, -0.07276,
+        -0.07367, -0.00605,  1.07602);
+
+    c = aces_in * c;
+    vec3 a = c * (c + 0.0245786) - 0.000090537;
+    vec3 b = c * (0.983729 * c + 0.4329510) + 0.238081;
+    return clamp(aces_out * (a / b), 0.0, 1.0);
+}
+
+vec3 hable_partial(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 hable(vec3 c) {
+    return hable_partial(c * 2.0) / hable_partial(vec3(HABLE_WHITE));
+}
+
+// Minimal AgX (Benjamin Wrensch's fit of Troy Sobotka's AgX base look)
+vec3 agx_contrast(vec3 x) {
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    return + 15.5 * x4 * x2
+           - 40.14 * x4 * x
+           + 31.96 * x4
+           - 6.868 * x2 * x
+           + 0.4298 * x2
+           + 0.1191 * x
+           - 0.00232;
+}
+
+vec3 agx(vec3 c) {
+    const mat3 agx_in = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    const mat3 agx_out = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    const float agx_min_ev = -12.47393;
+    const float agx_max_ev = 4.026069;
+
+    c = agx_in * c;
+    c = clamp(log2(max(c, 1e-10)), agx_min_ev, agx_max_ev);
+    c = (c - agx_min_ev) / (agx_max_ev - agx_min_ev);
+    c = agx_out * agx_contrast(c);
+    return pow(max(c, 0.0), vec3(2.2)); // back to linear, gamma is applied below
+}
+
+float get_exposure() {
+    if (auto_enabled == 0) {
+        return exposure;
+    }
+
+    // map the adapted log-average luminance to middle gray
+    float ev = texelFetch(adapted, ivec2(0), 0).r;
+    return 0.18 * exp2(ev_compensation - ev);
+}
+
+// the debug views only need the exposure, they skip tone mapping, bloom and grading
+#ifndef DEBUG_VIEW
+// linear rec.709 <-> LMS cone response, written row by row
+const mat3 LIN_2_LMS = mat3(
+    3.90405e-1, 5.49941e-1, 8.92632e-3,
+    7.08416e-2, 9.63172e-1, 1.35775e-3,
+    2.31082e-2, 1.28021e-1, 9.36245e-1);
+const mat3 LMS_2_LIN = mat3(
+     2.85847e+0, -1.62879e+0, -2.48910e-2,
+    -2.10182e-1,  1.15820e+0,  3.24281e-4,
+    -4.18120e-2, -1.18169e-1,  1.06867e+0);
+
+vec3 balance(vec3 c) {
+    vec3 lms = c * LIN_2_LMS;
+    return (lms * white_balance.rgb) * LMS_2_LIN;
+}
+
+// lift/gamma/gain, saturation and then the LUT, all on display encoded colors
+vec3 grade_color(vec3 c) {
+    c = grade_gain.rgb * (c + grade_lift.rgb * (1.0 - c));
+    c = pow(max(c, 0.0), 1.0 / grade_gamma.rgb);
+
+    float luma = dot(c, vec3(0.2126, 0.7152, 0.0722));
+    c = clamp(mix(vec3(luma), c, saturation), 0.0, 1.0);
+
+    if (lut_enabled != 0) {
+        vec3 uvw = (c - lut_domain_min.rgb) / (lut_domain_max.rgb - lut_domain_min.rgb);
+        uvw = clamp(uvw, 0.0, 1.0) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+        c = texture(lut, uvw).rgb;
+    }
+
+    return c;
+}
+
 vec3 to_ldr(vec3 lum) {
-    vec3 color = vec3(1.0) - exp(-lum * exposure);
-    return pow(color, vec3(1.0 / gamma));
+    vec3 color;
+    lum = max(balance(lum), 0.0) * get_exposure();
+
+    if (tonemap == TONEMAP_REINHARD) {
+        color = reinhard_extended(lum);
+    } else if (tonemap == TONEMAP_ACES) {
+        color = aces_fitted(lum);
+    } else if (tonemap == TONEMAP_HABLE) {
+        color = hable(lum);
+    } else if (tonemap == TONEMAP_AGX) {
+        color = agx(lum);
+    } else {
+        color = vec3(1.0) - exp(-lum);
+    }
+
+    return pow(clamp(color, 0.0, 1.0), vec3(1.0 / gamma));
+}
+#endif
+
+#ifdef DEBUG_VIEW
+const int DEBUG_POSITION = 1;
+const int DEBUG_NORMAL = 2;
+const int DEBUG_UV = 3;
+const int DEBUG_ALBEDO = 4;
+const int DEBUG_ROUGHNESS = 5;
+const int DEBUG_METALNESS = 6;
+const int DEBUG_SHADOW = 7;
+const int DEBUG_LIGHT = 8; // the regular pass with a single light, nothing to do here
+const int DEBUG_FALSE_COLOR = 9;
+
+#include "normal.glsl"
+#include "position.glsl"
+
+// exposed luminance in stops around middle gray, from blue (-4) through green (0) to red (+4)
+vec3 false_color(vec3 lum) {
+    float l = dot(lum * get_exposure(), vec3(0.2126, 0.7152, 0.0722));
+    float stops = log2(max(l, 1e-6) / 0.18);
+
+    if (stops < -4.0) return vec3(0.2, 0, 0.3);
+    if (stops > 4.0) return vec3(1);
+
+    float t = (stops + 4.0) / 8.0;
+    return clamp(vec3(
+        1.5 - abs(4.0 * t - 3.0),
+        1.5 - abs(4.0 * t - 2.0),
+        1.5 - abs(4.0 * t - 1.0)), 0.0, 1.0);
 }
 
 void main() {
+    float depth = texture(depth_tex, v_pos).r;
+    vec4 material = texture(layer_material, v_pos);
+    bool empty = depth <= 0.0;
+    vec3 color = vec3(0);
+
+    if (debug_view == DEBUG_SHADOW) {
+        // linear depth on a log scale, so that near and far detail are both visible
+        float n = shadow_clip.x;
+        float f = shadow_clip.y;
+        float z = texture(shadow_depth, v_pos).r * 2.0 - 1.0;
+        float d = 2.0 * n * f / (f + n - z * (f - n));
+        color = vec3(log(d / n) / log(f / n));
+    } else if (debug_view == DEBUG_FALSE_COLOR) {
+        color = false_color(texture(luminance, v_pos).rgb);
+    } else if (!empty) {
+        if (debug_view == DEBUG_POSITION) {
+            color = fract(world_pos(v_pos, depth));
+        } else if (debug_view == DEBUG_NORMAL) {
+            color = decode_normal(texture(layer_n, v_pos).xy) * 0.5 + 0.5;
+        } else if (debug_view == DEBUG_UV) {
+            color = vec3(fract(texture(velocity, v_pos).zw), 0);
+        } else if (debug_view == DEBUG_ALBEDO) {
+            color = texture(layer_albedo, v_pos).rgb;
+        } else if (debug_view == DEBUG_ROUGHNESS) {
+            color = material.rrr;
+        } else if (debug_view == DEBUG_METALNESS) {
+            color = material.ggg;
+        }
+    }
+
+    f_color = vec4(color, 1);
+}
+#else
+void main() {
+    vec3 bloom = texture(bloom_tex, v_pos).rgb;
+
+    if (bloom_debug != 0) {
+        f_color = vec4(to_ldr(bloom), 1);
+        return;
+    }
+
     f_color = texture(luminance, v_pos);
-    f_color.xyz = to_ldr(f_color.xyz);
-} 
\ No newline at end of file
+    f_color.xyz = grade_color(to_ldr(f_color.xyz + bloom * bloom_intensity));
+}
+#endif
@@ -0,0 +1,8 @@
+// per frame values, see LiveBlock in define.rs
+layout(std140) uniform live {
+    vec4 eye_pos;
+    float gamma;
+    float exposure;
+    float time;
+    int tonemap;
+};
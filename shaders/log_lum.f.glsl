@@ -0,0 +1,31 @@
+#version 410
+
+uniform sampler2D luminance;
+uniform sampler2D log_lum;
+
+layout(std140) uniform log_lum_reduce {
+    int log_lum_level;
+};
+
+in vec2 v_pos;
+out vec4 f_log_lum;
+
+// Level 0 is the log luminance of the frame, every other level is the average of a 2x2 block of the previous
+// level (bound as log_lum), so the last level holds the log-average of the whole frame.
+void main() {
+    if (log_lum_level == 0) {
+        vec3 lum = texture(luminance, v_pos).rgb;
+        float y = dot(lum, vec3(0.2126, 0.7152, 0.0722));
+        f_log_lum = vec4(log2(max(y, 1e-4)));
+        return;
+    }
+
+    ivec2 p = ivec2(gl_FragCoord.xy) * 2;
+
+    float sum = texelFetch(log_lum, p, 0).r
+              + texelFetch(log_lum, p + ivec2(1, 0), 0).r
+              + texelFetch(log_lum, p + ivec2(0, 1), 0).r
+              + texelFetch(log_lum, p + ivec2(1, 1), 0).r;
+
+    f_log_lum = vec4(sum * 0.25);
+}
@@ -0,0 +1,6 @@
+// world position from the depth buffer, the including shader declares inv_view_proj
+vec3 world_pos(vec2 uv, float depth) {
+    // reverse-Z clip depth goes from 0 to 1 already, only x and y need remapping
+    vec4 p = inv_view_proj * vec4(uv * 2.0 - 1.0, depth, 1);
+    return p.xyz / p.w;
+}
@@ -0,0 +1,88 @@
+#version 410
+
+const int SSAO_SAMPLES = 16;
+
+uniform sampler2D depth_tex;
+uniform sampler2D layer_n;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+layout(std140) uniform ssao {
+    vec4 blur_dir;
+    float ssao_radius;
+    float ssao_bias;
+    float ssao_intensity;
+    int ssao_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_ao;
+
+#include "normal.glsl"
+#include "position.glsl"
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// golden spiral over the +z hemisphere, packed closer to the center for the first samples
+vec3 kernel(int i) {
+    float fi = float(i) + 0.5;
+    float z = 1.0 - fi / float(SSAO_SAMPLES);
+    float r = sqrt(1.0 - z * z);
+    float phi = fi * 2.39996323;
+
+    float scale = fi / float(SSAO_SAMPLES);
+    scale = mix(0.1, 1.0, scale * scale);
+
+    return vec3(cos(phi) * r, sin(phi) * r, z) * scale;
+}
+
+void main() {
+    float d = texture(depth_tex, v_pos).r;
+
+    if (ssao_enabled == 0 || d <= 0.0) {
+        f_ao = vec4(1);
+        return;
+    }
+
+    vec3 pos = world_pos(v_pos, d);
+
+    // randomly rotated tangent frame around the normal
+    vec3 N = decode_normal(texture(layer_n, v_pos).xy);
+    float angle = hash(gl_FragCoord.xy) * 6.2831853;
+    vec3 rvec = vec3(cos(angle), sin(angle), 0.5);
+    vec3 T = normalize(rvec - N * dot(rvec, N));
+    vec3 B = cross(N, T);
+    mat3 tbn = mat3(T, B, N);
+
+    float depth = (view * vec4(pos, 1)).z;
+    float occlusion = 0.0;
+
+    for (int i = 0; i < SSAO_SAMPLES; i++) {
+        vec3 sample_pos = pos + tbn * kernel(i) * ssao_radius;
+        vec4 view_pos = view * vec4(sample_pos, 1);
+
+        vec4 clip = proj * view_pos;
+        vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+
+        // the background is at infinity and never occludes
+        float stored_d = texture(depth_tex, uv).r;
+        if (stored_d <= 0.0) continue;
+
+        vec3 stored = world_pos(uv, stored_d);
+        float stored_depth = (view * vec4(stored, 1)).z;
+
+        // view space looks down -z, so a larger z is closer to the camera
+        float range = smoothstep(0.0, 1.0, ssao_radius / abs(depth - stored_depth));
+        occlusion += (stored_depth >= view_pos.z + ssao_bias ? 1.0 : 0.0) * range;
+    }
+
+    float ao = 1.0 - occlusion / float(SSAO_SAMPLES);
+    f_ao = vec4(pow(ao, ssao_intensity));
+}
@@ -0,0 +1,54 @@
+#version 410
+
+const int BLUR_RADIUS = 4;
+
+uniform sampler2D depth_tex;
+uniform sampler2D ao_source;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+layout(std140) uniform ssao {
+    vec4 blur_dir;
+    float ssao_radius;
+    float ssao_bias;
+    float ssao_intensity;
+    int ssao_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_ao;
+
+#include "position.glsl"
+
+// the background is at infinity with reverse-Z, so it gets a finite stand in
+float view_depth(vec2 uv) {
+    float d = texture(depth_tex, uv).r;
+    if (d <= 0.0) return -1e6;
+    return (view * vec4(world_pos(uv, d), 1)).z;
+}
+
+// separable blur that does not bleed across depth discontinuities
+void main() {
+    vec2 step = blur_dir.xy / vec2(textureSize(ao_source, 0));
+    float center = view_depth(v_pos);
+
+    float total = 0.0;
+    float weight = 0.0;
+
+    for (int i = -BLUR_RADIUS; i <= BLUR_RADIUS; i++) {
+        vec2 uv = v_pos + step * float(i);
+        float spatial = exp(-float(i * i) / float(BLUR_RADIUS * BLUR_RADIUS));
+        float range = 1.0 / (1e-3 + abs(view_depth(uv) - center));
+        float w = spatial * min(range, 100.0);
+
+        total += texture(ao_source, uv).r * w;
+        weight += w;
+    }
+
+    f_ao = vec4(total / weight);
+}
@@ -0,0 +1,128 @@
+#version 410
+
+const int MAX_ITERATIONS = 96;
+
+uniform sampler2D depth_tex;
+uniform sampler2D layer_n;
+uniform sampler2D layer_albedo;
+uniform sampler2D layer_material;
+uniform sampler2D velocity;
+uniform sampler2D hiz;
+uniform sampler2D previous;
+
+layout(std140) uniform transform {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 inv_view_proj;
+};
+
+#include "live.glsl"
+
+layout(std140) uniform ssr {
+    float ssr_max_dist;
+    float ssr_thickness;
+    float ssr_intensity;
+    int ssr_enabled;
+    int hiz_level;
+    int hiz_levels;
+};
+
+in vec2 v_pos;
+out vec4 f_lum;
+
+#include "normal.glsl"
+#include "brdf.glsl"
+#include "position.glsl"
+
+// screen uv + device depth
+vec3 project(vec3 world) {
+    vec4 clip = proj * view * vec4(world, 1);
+    vec3 ndc = clip.xyz / clip.w;
+    return vec3(ndc.xy * 0.5 + 0.5, ndc.z);
+}
+
+// Walks the ray through the depth pyramid, taking bigger steps while the ray is in front of
+// everything in the current cell. Returns the hit uv, or a negative value on a miss.
+vec2 trace(vec3 start, vec3 end) {
+    vec3 d = end - start;
+    vec2 size = vec2(textureSize(hiz, 0));
+    float texel_t = 1.0 / max(max(abs(d.x) * size.x, abs(d.y) * size.y), 1.0);
+
+    int level = 0;
+    float t = texel_t * 2.0; // step off the starting surface
+
+    for (int i = 0; i < MAX_ITERATIONS; i++) {
+        float t_next = t + texel_t * exp2(float(level));
+        if (t_next > 1.0) {
+            if (level == 0) break;
+            level--;
+            continue;
+        }
+
+        vec3 p = start + d * t_next;
+        if (any(lessThan(p.xy, vec2(0))) || any(greaterThan(p.xy, vec2(1)))) break;
+
+        float z = texelFetch(hiz, ivec2(p.xy * vec2(textureSize(hiz, level))), level).r;
+
+        if (p.z > z) {
+            t = t_next;
+            level = min(level + 1, hiz_levels - 1);
+        } else if (level > 0) {
+            level--;
+        } else {
+            return (z - p.z < ssr_thickness) ? p.xy : vec2(-1);
+        }
+    }
+
+    return vec2(-1);
+}
+
+void main() {
+    float depth = texture(depth_tex, v_pos).r;
+
+    if (ssr_enabled == 0 || depth <= 0.0) {
+        f_lum = vec4(0);
+        return;
+    }
+
+    vec3 pos = world_pos(v_pos, depth);
+    vec4 material = texture(layer_material, v_pos);
+
+    float roughness = material.r;
+    if (roughness > 0.8) {
+        f_lum = vec4(0);
+        return;
+    }
+
+    vec3 albedo = pow(texture(layer_albedo, v_pos).rgb, vec3(2.2));
+    float metalness = material.g;
+    vec3 F0 = mix(vec3(0.04), albedo, metalness);
+
+    vec3 N = decode_normal(texture(layer_n, v_pos).xy);
+    vec3 V = normalize(eye_pos.xyz - pos);
+    vec3 R = reflect(-V, N);
+    vec3 F = fresnelSchlickRoughness(max(dot(N, V), 0.0), F0, roughness);
+
+    // keep the end of the ray in front of the camera
+    float dist = ssr_max_dist;
+    vec3 view_pos = (view * vec4(pos, 1)).xyz;
+    vec3 view_dir = (view * vec4(R, 0)).xyz;
+    if (view_dir.z > 0.0) {
+        dist = min(dist, (-view_pos.z - 0.1) / view_dir.z * 0.99);
+    }
+
+    // a miss adds nothing, the lighting pass has already added the ambient term
+    vec3 reflected = vec3(0);
+    vec2 hit = trace(project(pos), project(pos + R * dist));
+
+    if (hit.x >= 0.0) {
+        // fade out towards the screen edges so the reflection doesn't end abruptly
+        vec2 edge = smoothstep(0.0, 0.1, hit) * (1.0 - smoothstep(0.9, 1.0, hit));
+        vec2 prev_uv = hit - texture(velocity, hit).xy;
+        reflected = texture(previous, prev_uv).rgb * edge.x * edge.y;
+    }
+
+    float gloss = 1.0 - roughness / 0.8;
+    f_lum = vec4(reflected * F * gloss * gloss * ssr_intensity, 0);
+}
@@ -0,0 +1,40 @@
+#version 410
+
+uniform sampler2D current;
+uniform sampler2D history;
+uniform sampler2D velocity;
+
+layout(std140) uniform aa {
+    mat4 prev_view_proj;
+    mat4 curr_view_proj;
+    vec4 jitter;
+    float taa_feedback;
+    int fxaa_enabled;
+};
+
+in vec2 v_pos;
+out vec4 f_color;
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(current, 0));
+    vec3 curr = texture(current, v_pos).rgb;
+
+    // clamp the history to the current neighborhood to reject stale samples
+    vec3 lo = curr;
+    vec3 hi = curr;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec3 c = texture(current, v_pos + vec2(x, y) * texel).rgb;
+            lo = min(lo, c);
+            hi = max(hi, c);
+        }
+    }
+
+    vec2 prev_uv = v_pos - texture(velocity, v_pos).xy;
+    vec3 hist = clamp(texture(history, prev_uv).rgb, lo, hi);
+
+    bool offscreen = any(lessThan(prev_uv, vec2(0))) || any(greaterThan(prev_uv, vec2(1)));
+    float feedback = offscreen ? 0.0 : taa_feedback;
+
+    f_color = vec4(mix(curr, hist, feedback), 1);
+}
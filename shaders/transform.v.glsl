@@ -4,6 +4,8 @@ layout(std140) uniform transform {
     mat4 model;
     mat4 view;
     mat4 proj;
+    mat4 inv_view_proj;
+    mat4 normal_matrix;
 };
 
 in vec3 a_pos;
@@ -26,17 +28,49 @@ in vec2 a_tex;
 out vec2 v_tex;
 #endif
 
+#ifdef INSTANCED
+in vec4 a_inst0;
+in vec4 a_inst1;
+in vec4 a_inst2;
+in vec4 a_inst3;
+in vec3 a_inst_nor0;
+in vec3 a_inst_nor1;
+in vec3 a_inst_nor2;
+#endif
+
+#ifdef MATERIAL
+in vec2 a_inst_material;
+out vec2 v_material;
+#endif
+
 void main() {
-    vec4 p = model * vec4(a_pos, 1);
+    // normals go through the inverse transpose (normal_matrix), so non-uniform scale keeps them
+    // perpendicular to the surface
+    #ifdef INSTANCED
+    mat4 world = mat4(a_inst0, a_inst1, a_inst2, a_inst3) * model;
+    mat3 nor_world = mat3(a_inst_nor0, a_inst_nor1, a_inst_nor2) * mat3(normal_matrix);
+    #else
+    mat4 world = model;
+    mat3 nor_world = mat3(normal_matrix);
+    #endif
+
+    vec4 p = world * vec4(a_pos, 1);
     v_pos = p.xyz;
 
     #ifdef NORM
-    v_norm = (model * vec4(a_nor, 0)).xyz;
+    v_norm = normalize(nor_world * a_nor);
     #endif
 
-    #ifdef TAN
-    v_tan = normalize((model * vec4(a_tan, 0)).xyz);
-    v_bitan = normalize((model * vec4(a_btn, 0)).xyz);
+    // tangents lie in the surface, so they go through world itself, and are then made
+    // perpendicular to the normal again
+    #if defined(TAN) && defined(NORM)
+    vec3 t = (world * vec4(a_tan, 0)).xyz;
+    vec3 b = (world * vec4(a_btn, 0)).xyz;
+    v_tan = normalize(t - v_norm * dot(v_norm, t));
+    v_bitan = normalize(b - v_norm * dot(v_norm, b));
+    #elif defined(TAN)
+    v_tan = normalize((world * vec4(a_tan, 0)).xyz);
+    v_bitan = normalize((world * vec4(a_btn, 0)).xyz);
     #endif
 
     #ifdef TEX
@@ -44,6 +78,10 @@ void main() {
     v_tex.y = 1 - v_tex.y;
     #endif
 
+    #ifdef MATERIAL
+    v_material = a_inst_material;
+    #endif
+
     #ifdef VIEWPROJ
     gl_Position = proj * view * p;
     #else
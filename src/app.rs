@@ -1,5 +1,5 @@
 use cgmath::prelude::*;
-use cgmath::{Point3, Matrix4, Deg, PerspectiveFov, vec3};
+use cgmath::{Point3, Vector2, Matrix4, Deg, vec2, vec3};
 use image;
 use gfx;
 use gfx::traits::{FactoryExt};
@@ -8,13 +8,21 @@ use gfx::format;
 use gfx::handle::*;
 use gfx_app::{self, ApplicationBase};
 use winit::{self, Event};
-use std::time::Instant;
+use std::time::{Instant, Duration};
 use std::path::{Path, PathBuf};
 
 use shaders;
 use define::{self, VertexSlice};
-use camera::{Camera, BasicCamera, ArcBall};
-use wavefront::{open_obj};
+use camera::{self, Camera, ArcBall, FlyCamera, CameraMode, PhysicalCamera, ReverseZ, Ray, Plane};
+use wavefront::{open_obj, WavefrontMesh, Group};
+use graph::{self, Graph, Trs, Aabb, CullStats};
+use rig::{self, Rig, Light};
+use post::{self, ToneMap, AutoExposure, Bloom, Ssao, Ssr, AntiAlias, Dof, Grade, DebugView};
+use lut::{self, Lut};
+use scene::{self, Scene, SceneObject, CameraPose, Board};
+use keyframes::{self, CameraPath, CameraKey, Interpolation};
+use pick::{self, Bvh, Material};
+use shapes;
 use rand::{Rng, ThreadRng, thread_rng};
 
 pub struct App<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
@@ -24,24 +32,60 @@ pub struct App<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
     mouse_pos: Option<(i32, i32)>,
     orbit_diff: (f32, f32, f32),
     left_down: bool,
-    cam: ArcBall<PerspectiveFov<f32>, Deg<f32>>,
+    middle_down: bool,
+    pan_diff: (f32, f32),
+    click_pos: Option<(i32, i32)>, // where the left button went down
+    pick: Option<Vector2<f32>>, // a click waiting to be picked, in device coordinates
+    status: Option<String>, // what the last pick found, until the window title shows it
+    cam: ArcBall<ReverseZ, Deg<f32>>,
+    cam_mode: CameraMode,
+    fly: FlyCamera<ReverseZ, Deg<f32>>, // only used in fly mode
+    fly_keys: [bool; 6], // forward, back, left, right, up, down
+    fly_speed: f32,
+    turntable_speed: f32, // degrees per second
+    path: CameraPath,
+    path_file: PathBuf,
+    playing: Option<f64>, // when the camera path started playing
+    fixed_step: Option<f64>, // seconds per frame, for a clock that is the same every run
     start_time: Instant,
-    exposure: f32,
+    lens: PhysicalCamera,
+    dof: Dof,
+    grade: Grade,
+    lut: Lut,
+    debug_view: DebugView,
+    debug_light: usize,
     gamma: f32,
-    current: usize,
-    lights: Vec<PointLight>,
+    tonemap: ToneMap,
+    auto_exposure: AutoExposure,
+    bloom: Bloom,
+    ssao: Ssao,
+    ssr: Ssr,
+    aa: AntiAlias,
+    frame: usize,
+    prev_view_proj: Matrix4<f32>,
+    last_time: f64,
+    current: usize, // the object the camera orbits
+    rig: Rig,
+    lights: Vec<Light>,
     rng: ThreadRng,
-    inital_color: (bool, [f32; 4], [f32; 4]),
+    random_colors: bool,
+    scene: Scene, // what the scene was loaded from, F5 saves it with the current settings
+    save_path: PathBuf,
+    culling: bool,
+    cull_stats: [CullStats; 2], // main pass, all shadow passes together
 
     //===========//
     // App Stuff //
     //===========//
     encoder: gfx::Encoder<R, C>,
+    zero_to_one: bool, // clip space depth goes from 0 to 1 (glClipControl) instead of -1 to 1
 
     //========//
     // Models //
     //========//
     objects: Vec<Object<R>>,
+    ground: Option<Object<R>>,
+    graph: Graph,
     quad: VertexSlice<R, define::V>,
 
     //===========//
@@ -50,7 +94,20 @@ pub struct App<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
     deferred_pso: gfx::PipelineState<R, define::deferred::Meta>,
     pbr_pso: gfx::PipelineState<R, define::pbr::Meta>,
     ldr_pso: gfx::PipelineState<R, define::ldr::Meta>,
+    ldr_debug_pso: gfx::PipelineState<R, define::ldr_debug::Meta>,
     shadow_pso: gfx::PipelineState<R, define::shadow::Meta>,
+    log_lum_pso: gfx::PipelineState<R, define::log_lum::Meta>,
+    adapt_pso: gfx::PipelineState<R, define::adapt::Meta>,
+    ssao_pso: gfx::PipelineState<R, define::ssao::Meta>,
+    ssao_blur_pso: gfx::PipelineState<R, define::ssao_blur::Meta>,
+    taa_pso: gfx::PipelineState<R, define::taa::Meta>,
+    hiz_pso: gfx::PipelineState<R, define::hiz::Meta>,
+    ssr_pso: gfx::PipelineState<R, define::ssr::Meta>,
+    fxaa_pso: gfx::PipelineState<R, define::fxaa::Meta>,
+    bloom_down_pso: gfx::PipelineState<R, define::bloom_down::Meta>,
+    bloom_up_pso: gfx::PipelineState<R, define::bloom_up::Meta>,
+    dof_pso: gfx::PipelineState<R, define::dof::Meta>,
+    shader_watch: shaders::Watcher, // rebuilds the pipelines above when their files change
 
     //===============//
     // Pipeline Data //
@@ -58,53 +115,249 @@ pub struct App<R: gfx::Resources, C: gfx::CommandBuffer<R>> {
     deferred_data: define::deferred::Data<R>,
     pbr_data: define::pbr::Data<R>,
     ldr_data: define::ldr::Data<R>,
+    ldr_debug_data: define::ldr_debug::Data<R>,
     shadow_data: define::shadow::Data<R>,
+    log_lum_data: define::log_lum::Data<R>,
+    adapt_data: define::adapt::Data<R>,
+    ssao_data: define::ssao::Data<R>,
+    ssao_blur_data: define::ssao_blur::Data<R>,
+    taa_data: define::taa::Data<R>,
+    hiz_data: define::hiz::Data<R>,
+    ssr_data: define::ssr::Data<R>,
+    fxaa_data: define::fxaa::Data<R>,
+    bloom_down_data: define::bloom_down::Data<R>,
+    bloom_up_data: define::bloom_up::Data<R>,
+    dof_data: define::dof::Data<R>,
+
+    // ambient occlusion, ping-ponged by the blur passes
+    ssao_layers: [ViewPair<R, define::LayerFormat>; 2],
+
+    // HDR luminance, swapped every frame so reflections can read the last one
+    luminance: [ViewPair<R, define::LayerFormat>; 2],
+    luminance_index: usize,
+
+    // luminance after depth of field
+    dof_layer: ViewPair<R, define::LayerFormat>,
+
+    // log luminance, each level averaging 2x2 texels of the one before
+    log_lum_chain: Vec<ViewPair<R, define::LayerFormat>>,
+
+    // one render target per depth pyramid level, and a copy of each level to reduce from
+    hiz_levels: Vec<RenderTargetView<R, define::LayerFormat>>,
+    hiz_chain: Vec<ViewPair<R, define::LayerFormat>>,
+
+    // tone mapped image before anti-aliasing, and the TAA history
+    ldr_layer: ViewPair<R, define::ColorFormat>,
+    history: [ViewPair<R, define::LayerFormat>; 2],
+    history_index: usize,
+
+    // progressively smaller bloom buffers, the first is half the window size
+    bloom_chain: Vec<ViewPair<R, define::LayerFormat>>,
+    // adapted luminance, swapped every frame
+    adapted: [ViewPair<R, define::LayerFormat>; 2],
+    adapted_index: usize,
 }
 
 struct Object<R: gfx::Resources> {
     pub mesh: VertexSlice<R, define::Vtnt>,
+    pub node: usize, // root of the object in the scene graph
+    pub parts: Vec<(usize, gfx::Slice<R>)>, // nodes that draw a range of the mesh
+    pub placements: Vec<Matrix4<f32>>, // one per instance, applied on top of the world matrices
+    pub material_scales: Vec<[f32; 2]>, // roughness and metalness multipliers, one per instance
+    pub instances: Buffer<R, define::Instance>, // the placements and scales for the GPU
+    pub bvhs: Vec<Bvh>, // one per part, for picking
+    pub material: Material, // what picking reports
     pub sampler: Sampler<R>,
     pub normal: ShaderResourceView<R, [f32; 4]>,
     pub albedo: ShaderResourceView<R, [f32; 4]>,
     pub roughness: ShaderResourceView<R, [f32; 4]>,
     pub metalness: ShaderResourceView<R, [f32; 4]>,
+    pub ao: ShaderResourceView<R, [f32; 4]>,
 }
 
 impl<R: gfx::Resources> Object<R> {
-    pub fn apply_to_data(&self, deferred: &mut define::deferred::Data<R>, pbr: &mut define::pbr::Data<R>, shadow: &mut define::shadow::Data<R>) {
+    pub fn apply_to_data(&self, deferred: &mut define::deferred::Data<R>, shadow: &mut define::shadow::Data<R>) {
         deferred.verts = self.mesh.0.clone();
         shadow.verts = self.mesh.0.clone();
+        deferred.instances = self.instances.clone();
+        shadow.instances = self.instances.clone();
 
         deferred.normal = (self.normal.clone(), self.sampler.clone());
-        pbr.albedo = (self.albedo.clone(), self.sampler.clone());
-        pbr.roughness = (self.roughness.clone(), self.sampler.clone());
-        pbr.metalness = (self.metalness.clone(), self.sampler.clone());
+        deferred.albedo = (self.albedo.clone(), self.sampler.clone());
+        deferred.roughness = (self.roughness.clone(), self.sampler.clone());
+        deferred.metalness = (self.metalness.clone(), self.sampler.clone());
+        deferred.ao = (self.ao.clone(), self.sampler.clone());
+    }
+
+    /// Whether any instance of a node could be inside the frustum, nodes without bounds always are.
+    pub fn visible(&self, graph: &Graph, node: usize, frustum: &[Plane]) -> bool {
+        match graph.nodes[node].bounds {
+            Some(b) => self.placements.iter().any(|m| b.transform(m).in_frustum(frustum)),
+            None => true,
+        }
+    }
+
+    /// The world bounds of a node around all of its instances.
+    pub fn bounds(&self, graph: &Graph, node: usize) -> Option<Aabb> {
+        graph.nodes[node].bounds.map(|b| {
+            self.placements[1..].iter()
+                .fold(b.transform(&self.placements[0]), |acc, m| acc.union(&b.transform(m)))
+        })
     }
 }
 
-struct PointLight {
-    pub base_angle: Deg<f32>,
-    pub color: [f32; 4],
-    pub ambient: [f32; 4],
+/// Uploads instance placements as columns for `define::Instance`, along with their material scales.
+fn build_instances<R, C, F>(factory: &mut F, placements: &[Matrix4<f32>], scales: &[[f32; 2]]) -> Buffer<R, define::Instance>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    let data: Vec<_> = placements.iter().zip(scales).map(|(m, &scale)| {
+        let n = graph::normal_matrix(m);
+        define::Instance {
+            a_inst0: m.x.into(),
+            a_inst1: m.y.into(),
+            a_inst2: m.z.into(),
+            a_inst3: m.w.into(),
+            a_inst_nor0: n.x.truncate().into(),
+            a_inst_nor1: n.y.truncate().into(),
+            a_inst_nor2: n.z.truncate().into(),
+            a_inst_material: scale,
+        }
+    }).collect();
+    factory.create_vertex_buffer(&data)
 }
 
-impl PointLight {
-    pub fn animate_camera(&self, time: f32) -> BasicCamera<PerspectiveFov<f32>> {
-        let theta = self.base_angle + Deg(time * 30.);
-
-        let camera = ArcBall {
-            origin: Point3::new(0., 0., 0.),
-            theta: theta,
-            phi: Deg((theta + Deg(time * 13.)).sin() * 45.),
-            dist: 7.,
-            projection: PerspectiveFov {
-                fovy: Deg(20.).into(),
-                aspect: 1., 
-                near: 0.1, far: 100.
-            },
-        };
+/// A flat, square plane facing up, with a plain rough material.
+fn build_ground<R, C, F>(factory: &mut F, graph: &mut Graph, height: f32, sampler: Sampler<R>) -> Object<R>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    use define::Vtnt;
+
+    let size = 20.;
+    let vert = |x: f32, z: f32| Vtnt {
+        a_pos: [x * size, 0., z * size],
+        a_tex: [x * size * 0.5, z * size * 0.5],
+        a_nor: [0., 1., 0.],
+        a_tan: [1., 0., 0.],
+        a_btn: [0., 0., -1.],
+    };
+    let verts = [vert(-1., -1.), vert(-1., 1.), vert(1., -1.), vert(1., 1.)];
+    let (albedo, roughness, metalness) = ([150, 150, 150, 255], [200, 200, 200, 255], [0, 0, 0, 255]);
+
+    let mesh = factory.create_vertex_buffer_with_slice(&verts, &[0u16, 1, 2, 2, 1, 3][..]);
+    let bounds = Aabb {
+        min: Point3::new(-size, 0., -size),
+        max: Point3::new(size, 0., size),
+    };
+    let node = graph.add("ground".to_string(), None, Trs {
+        translation: vec3(0., height, 0.),
+        .. Trs::identity()
+    }, Some(bounds));
+
+    let placements = vec![Matrix4::identity()];
+    let material_scales = vec![[1., 1.]];
+
+    Object {
+        parts: vec![(node, gfx::Slice {
+            instances: Some((1, 0)),
+            .. mesh.1.clone()
+        })],
+        instances: build_instances(factory, &placements, &material_scales),
+        placements: placements,
+        material_scales: material_scales,
+        bvhs: vec![Bvh::new(&verts, &[0, 1, 2, 2, 1, 3])],
+        material: Material::solid(albedo, roughness, metalness),
+        mesh: mesh,
+        node: node,
+        normal: solid_texture(factory, [128, 128, 255, 255]),
+        albedo: solid_texture(factory, albedo),
+        roughness: solid_texture(factory, roughness),
+        metalness: solid_texture(factory, metalness),
+        ao: solid_texture(factory, [255, 255, 255, 255]),
+        sampler: sampler,
+    }
+}
 
-        camera.to_camera()
+/// One sphere mesh drawn once per cell of the board, with white roughness and metalness maps
+/// scaled per instance.
+fn build_board<R, C, F>(factory: &mut F, graph: &mut Graph, board: &Board, sampler: Sampler<R>) -> Object<R>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    let (verts, indices) = shapes::uv_sphere(board.spacing * 0.4, 48, 24);
+    let b = |v: f32| (v.max(0.).min(1.) * 255. + 0.5) as u8;
+    let albedo = [b(board.albedo[0]), b(board.albedo[1]), b(board.albedo[2]), 255];
+    let white = [255, 255, 255, 255];
+
+    let mesh = factory.create_vertex_buffer_with_slice(&verts, &indices[..]);
+    let node = graph.add("board".to_string(), None, Trs::identity(), None);
+    let part = graph.add("sphere".to_string(), Some(node), Trs::identity(), Some(shapes::bounds(&verts)));
+
+    // roughness goes up along the columns and metalness along the rows
+    let n = board.size;
+    let step = |i: usize| if n > 1 { i as f32 / (n - 1) as f32 } else { 1. };
+    let offset = |i: usize| (i as f32 - (n - 1) as f32 * 0.5) * board.spacing;
+    let mut placements = Vec::with_capacity(n * n);
+    let mut material_scales = Vec::with_capacity(n * n);
+    for row in 0..n {
+        for column in 0..n {
+            placements.push(Matrix4::from_translation(vec3(offset(column), offset(row), 0.)));
+            material_scales.push([step(column), step(row)]);
+        }
+    }
+
+    Object {
+        parts: vec![(part, gfx::Slice {
+            instances: Some((placements.len() as u32, 0)),
+            .. mesh.1.clone()
+        })],
+        instances: build_instances(factory, &placements, &material_scales),
+        placements: placements,
+        material_scales: material_scales,
+        bvhs: vec![Bvh::new(&verts, &indices)],
+        material: Material::solid(albedo, white, white),
+        mesh: mesh,
+        node: node,
+        normal: solid_texture(factory, [128, 128, 255, 255]),
+        albedo: solid_texture(factory, albedo),
+        roughness: solid_texture(factory, white),
+        metalness: solid_texture(factory, white),
+        ao: solid_texture(factory, white),
+        sampler: sampler,
+    }
+}
+
+/// Compiles a triangle list pipeline, failing with the shader log or the linker's complaint.
+/// Errors name the pass, since the compiler log only names files.
+fn build_pso<R, F, I>(factory: &mut F, name: &str, shaders: Result<gfx::ShaderSet<R>, gfx::shade::core::CreateShaderError>,
+                      rasterizer: gfx::state::Rasterizer, init: I) -> Result<gfx::PipelineState<R, I::Meta>, String>
+    where R: gfx::Resources,
+          F: gfx::Factory<R>,
+          I: gfx::pso::PipelineInit,
+{
+    let shaders = shaders.map_err(|e| format!("Could not build the {} shaders: {}", name, e))?;
+    factory.create_pipeline_state(&shaders, gfx::Primitive::TriangleList, rasterizer, init)
+        .map_err(|e| format!("Could not build the {} pipeline: {}", name, e))
+}
+
+fn fill() -> gfx::state::Rasterizer {
+    gfx::state::Rasterizer::new_fill()
+}
+
+/// Shadows are rendered from back faces to keep acne off the lit side.
+fn shadow_rasterizer() -> gfx::state::Rasterizer {
+    use gfx::state::*;
+
+    Rasterizer {
+        front_face: FrontFace::CounterClockwise,
+        cull_face: CullFace::Front,
+        method: RasterMethod::Fill,
+        offset: None,
+        samples: None,
     }
 }
 
@@ -128,6 +381,73 @@ fn build_layer<R, C, F, T>(factory: &mut F, w: texture::Size, h: texture::Size)
     }
 }
 
+/// Log luminance at 256x256, halved down to a single texel. Every level is its own texture so
+/// the pass writing one level never samples the texture it renders to.
+fn build_log_lum_chain<R, C, F>(factory: &mut F) -> Vec<ViewPair<R, define::LayerFormat>>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    (0..9).map(|i| build_layer(factory, 256 >> i, 256 >> i)).collect()
+}
+
+fn build_bloom_chain<R, C, F>(factory: &mut F, w: texture::Size, h: texture::Size) -> Vec<ViewPair<R, define::LayerFormat>>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    (1..post::BLOOM_LEVELS + 1)
+        .map(|i| build_layer(factory, ::std::cmp::max(w >> i, 1), ::std::cmp::max(h >> i, 1)))
+        .collect()
+}
+
+/// A full size depth pyramid with a render target for each level, and a separate texture per
+/// level for the reduction to read, since sampling the pyramid while rendering into one of its
+/// levels is a feedback loop.
+fn build_hiz<R, C, F>(factory: &mut F, w: texture::Size, h: texture::Size)
+    -> (ShaderResourceView<R, [f32; 4]>, Vec<RenderTargetView<R, define::LayerFormat>>, Vec<ViewPair<R, define::LayerFormat>>)
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    let mut levels = 1;
+    while levels < 8 && (w >> levels) > 0 && (h >> levels) > 0 { levels += 1 }
+
+    let kind = texture::Kind::D2(w, h, texture::AaMode::Single);
+    let bind = gfx::SHADER_RESOURCE | gfx::RENDER_TARGET;
+    let ctype = Some(gfx::format::ChannelType::Float);
+
+    let tex = factory.create_texture(kind, levels, bind, gfx::memory::Usage::Data, ctype).unwrap();
+    let resource = factory.view_texture_as_shader_resource::<define::LayerFormat>(
+        &tex, (0, levels - 1), gfx::format::Swizzle::new()).unwrap();
+    let targets = (0..levels)
+        .map(|l| factory.view_texture_as_render_target(&tex, l, None).unwrap())
+        .collect();
+    let chain = (0..levels)
+        .map(|l| build_layer(factory, w >> l, h >> l))
+        .collect();
+
+    (resource, targets, chain)
+}
+
+fn solid_texture<R, C, F>(factory: &mut F, color: [u8; 4]) -> ShaderResourceView<R, [f32; 4]>
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    factory.create_texture_immutable_u8::<(format::R8_G8_B8_A8, format::Unorm)>(
+        texture::Kind::D2(1, 1, texture::AaMode::Single),
+        &[&color]
+    ).unwrap().1
+}
+
+fn read_image<P: AsRef<Path>>(path: P) -> image::RgbaImage {
+    use std::io::*;
+    use std::fs::File;
+
+    image::load(BufReader::new(File::open(path).expect("Image not found")), image::PNG).unwrap().to_rgba()
+}
+
 fn load_image<R, C, F, T, P>(factory: &mut F, path: P) -> (Texture<R, T::Surface>, ShaderResourceView<R, T::View>)
     where F: gfx_app::Factory<R, CommandBuffer=C>,
           R: gfx::Resources + 'static,
@@ -135,28 +455,32 @@ fn load_image<R, C, F, T, P>(factory: &mut F, path: P) -> (Texture<R, T::Surface
           P: AsRef<Path>,
           T: format::TextureFormat,
 {
-    use std::io::*;
-    use std::fs::File;
+    upload_image::<_, _, _, T>(factory, &read_image(path))
+}
 
-    let image = image::load(BufReader::new(File::open(path).expect("Image not found")), image::PNG).unwrap().to_rgba();
+fn upload_image<R, C, F, T>(factory: &mut F, image: &image::RgbaImage) -> (Texture<R, T::Surface>, ShaderResourceView<R, T::View>)
+    where F: gfx_app::Factory<R, CommandBuffer=C>,
+          R: gfx::Resources + 'static,
+          C: gfx::CommandBuffer<R> + Send + 'static,
+          T: format::TextureFormat,
+{
     let dim = image.dimensions();
 
     factory.create_texture_immutable_u8::<T>(
         texture::Kind::D2(dim.0 as u16, dim.1 as u16, texture::AaMode::Single),
-        &[&image]
+        &[image]
     ).expect("Could not upload texture")  // TODO: Result
 }
 
+fn get_rgb(arg: ::clap::Values) -> [f32; 3] {
+    let v: Vec<f32> = arg.map(|v| v.parse().expect("Could not parse color component")).collect();
+    [v[0], v[1], v[2]]
+}
+
 fn get_color(mut arg: ::clap::Values) -> Result<[f32; 4], &'static str> {
     let c = arg.next().ok_or("No color provided")?;
-    if c.len() != 6 { return Err("Invalid color format (not 6 chars)") }
-    let z = u64::from_str_radix(c, 16).map_err(|_| "Invalid color format (not hex)")?;
-    let mut rgb = [
-        ((z >> 16) & 0xFF) as f32 / 255.,
-        ((z >> 8 ) & 0xFF) as f32 / 255.,
-        ( z        & 0xFF) as f32 / 255.,
-        1.,
-    ];
+    let c = rig::parse_hex(c).ok_or("Invalid color format (not 6 hex chars)")?;
+    let mut rgb = [c[0], c[1], c[2], 1.];
 
     if let Some(v) = arg.next() {
         rgb[3] *= v.parse().map_err(|_| "Second parameter is not a float")?
@@ -165,7 +489,17 @@ fn get_color(mut arg: ::clap::Values) -> Result<[f32; 4], &'static str> {
     Ok(rgb)
 }
 
-fn get_args() -> (Vec<PathBuf>, usize, [f32; 4], [f32; 4]) {
+struct Args {
+    scene: Scene,
+    save_path: PathBuf,
+    debug_view: DebugView,
+    path: CameraPath,
+    path_file: PathBuf,
+    play: bool,
+    fixed_step: Option<f64>,
+}
+
+fn get_args() -> Args {
     use clap::{App, Arg};
 
     let args = App::new("PBR Demo")
@@ -174,36 +508,462 @@ fn get_args() -> (Vec<PathBuf>, usize, [f32; 4], [f32; 4]) {
             .short("o")
             .long("objects")
             .help("list of directories, each one containing model.obj and several PBR textures")
-            .required(true)
+            .required_unless_one(&["scene", "board"])
             .min_values(1))
+        .arg(Arg::with_name("scene")
+            .long("scene")
+            .help("scene file (see scenes/), replaces the object, light, camera and post processing options")
+            .takes_value(true))
+        .arg(Arg::with_name("save")
+            .long("save")
+            .help("where F5 saves the current scene")
+            .default_value("saved.scene"))
+        .arg(Arg::with_name("spacing")
+            .long("spacing")
+            .help("distance between neighbouring objects")
+            .default_value("3.5"))
+        .arg(Arg::with_name("normalize")
+            .long("normalize")
+            .help("recenter every model and scale it to this bounding radius")
+            .takes_value(true))
+        .arg(Arg::with_name("shape")
+            .long("shape")
+            .help("draw every object as a built in mesh with its own textures")
+            .possible_values(&["sphere", "cube", "plane"])
+            .takes_value(true))
+        .arg(Arg::with_name("board")
+            .long("board")
+            .help("add a board of spheres this many on a side, sweeping roughness against metalness")
+            .takes_value(true))
+        .arg(Arg::with_name("ground")
+            .long("ground")
+            .help("draw a ground plane at this height")
+            .allow_hyphen_values(true)
+            .takes_value(true))
         .arg(Arg::with_name("lights")
             .short("l")
             .long("lights")
-            .help("how many point lights")
+            .help("how many orbiting point lights (ignored if --rig is given)")
             .default_value("5"))
+        .arg(Arg::with_name("rig")
+            .short("r")
+            .long("rig")
+            .help("light rig file (see rigs/)")
+            .takes_value(true))
         .arg(Arg::with_name("ambient")
             .short("a")
             .long("ambient")
             .help("ambient color")
             .min_values(1)
             .max_values(2)
-            .default_value("4d479b"))
+            .default_value(scene::DEFAULT_AMBIENT))
         .arg(Arg::with_name("color")
             .short("c")
             .long("color")
             .help("light color")
             .min_values(1)
             .max_values(2)
-            .default_value("e0bd91"))
+            .default_value(scene::DEFAULT_LIGHT_COLOR))
+        .arg(Arg::with_name("tonemap")
+            .short("t")
+            .long("tonemap")
+            .help("tone mapping operator")
+            .possible_values(&["exponential", "reinhard", "aces", "hable", "agx"])
+            .default_value("exponential"))
+        .arg(Arg::with_name("auto_exposure")
+            .long("auto-exposure")
+            .help("adapt exposure to the average scene luminance"))
+        .arg(Arg::with_name("ev_range")
+            .long("ev-range")
+            .help("min and max log2 luminance the auto exposure adapts to [default: -10 10]")
+            .number_of_values(2)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("ev_compensation")
+            .long("ev-compensation")
+            .help("auto exposure compensation in stops")
+            .allow_hyphen_values(true)
+            .default_value("0"))
+        .arg(Arg::with_name("bloom_intensity")
+            .long("bloom-intensity")
+            .help("how much bloom is added to the image, 0 leaves it off until \"b\" turns it on at 0.05")
+            .default_value("0"))
+        .arg(Arg::with_name("bloom_radius")
+            .long("bloom-radius")
+            .help("spread of the bloom upsampling filter, in texels")
+            .default_value("1"))
+        .arg(Arg::with_name("bloom_threshold")
+            .long("bloom-threshold")
+            .help("luminance above which pixels start to bloom")
+            .default_value("10"))
+        .arg(Arg::with_name("ssao")
+            .long("ssao")
+            .help("enable screen-space ambient occlusion"))
+        .arg(Arg::with_name("ssao_radius")
+            .long("ssao-radius")
+            .help("world space radius of the ambient occlusion samples")
+            .default_value("0.3"))
+        .arg(Arg::with_name("ssao_intensity")
+            .long("ssao-intensity")
+            .help("ambient occlusion contrast (0 disables it)")
+            .default_value("1"))
+        .arg(Arg::with_name("ssr")
+            .long("ssr")
+            .help("enable screen-space reflections"))
+        .arg(Arg::with_name("ssr_intensity")
+            .long("ssr-intensity")
+            .help("strength of screen-space reflections (0 disables them)")
+            .default_value("1"))
+        .arg(Arg::with_name("aa")
+            .long("aa")
+            .help("anti-aliasing mode")
+            .possible_values(&["off", "fxaa", "taa"])
+            .default_value("off"))
+        .arg(Arg::with_name("focal_length")
+            .long("focal-length")
+            .help("lens focal length in mm (on a 36x24mm sensor)")
+            .default_value("38"))
+        .arg(Arg::with_name("f_stop")
+            .long("f-stop")
+            .help("aperture f-number, controls exposure and depth of field")
+            .default_value("2.8"))
+        .arg(Arg::with_name("shutter")
+            .long("shutter")
+            .help("shutter time in seconds")
+            .default_value("0.25"))
+        .arg(Arg::with_name("iso")
+            .long("iso")
+            .help("sensor sensitivity")
+            .default_value("400"))
+        .arg(Arg::with_name("dof")
+            .long("dof")
+            .help("enable depth of field"))
+        .arg(Arg::with_name("focus")
+            .long("focus")
+            .help("focus distance (right click focuses on the point under the cursor instead)")
+            .default_value("4"))
+        .arg(Arg::with_name("lut")
+            .long("lut")
+            .help("color grading lookup table (.cube), applied after tone mapping")
+            .takes_value(true))
+        .arg(Arg::with_name("lift")
+            .long("lift")
+            .help("color grading lift (shadows), per channel [default: 0 0 0]")
+            .number_of_values(3)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("grade_gamma")
+            .long("grade-gamma")
+            .help("color grading gamma (midtones), per channel [default: 1 1 1]")
+            .number_of_values(3))
+        .arg(Arg::with_name("gain")
+            .long("gain")
+            .help("color grading gain (highlights), per channel [default: 1 1 1]")
+            .number_of_values(3))
+        .arg(Arg::with_name("saturation")
+            .long("saturation")
+            .help("color saturation (0 is grayscale)")
+            .default_value("1"))
+        .arg(Arg::with_name("temperature")
+            .long("temperature")
+            .help("white balance temperature, -1 (cool) to 1 (warm)")
+            .allow_hyphen_values(true)
+            .default_value("0"))
+        .arg(Arg::with_name("tint")
+            .long("tint")
+            .help("white balance tint, -1 (green) to 1 (magenta)")
+            .allow_hyphen_values(true)
+            .default_value("0"))
+        .arg(Arg::with_name("debug_view")
+            .long("debug-view")
+            .help("show an intermediate buffer instead of the final image")
+            .possible_values(&["off", "position", "normal", "uv", "albedo", "roughness", "metalness",
+                               "shadow", "light", "false_color"])
+            .default_value("off"))
+        .arg(Arg::with_name("path")
+            .long("path")
+            .help("camera path file, \"p\" plays it and \"k\" adds the current view to it")
+            .default_value("camera.path"))
+        .arg(Arg::with_name("interpolation")
+            .long("interpolation")
+            .help("how the camera moves between path keys")
+            .possible_values(&["catmull_rom", "bezier"])
+            .takes_value(true))
+        .arg(Arg::with_name("play")
+            .long("play")
+            .help("play the camera path from the first frame"))
+        .arg(Arg::with_name("fixed_step")
+            .long("fixed-step")
+            .help("seconds per frame instead of the real clock, so every run renders the same frames")
+            .takes_value(true))
     .get_matches();
 
-    (
-        args.values_of("object").unwrap().map(|v| PathBuf::from(v)).collect(),
-        args.value_of("lights").map(|v| v.parse()).unwrap().expect("Could not parse light count"),
-        get_color(args.values_of("ambient").unwrap()).expect("Could not parse ambient color arg"),
-        get_color(args.values_of("color").unwrap()).expect("Could not parse light color arg"),
-    )
+    let path_file = PathBuf::from(args.value_of("path").unwrap());
+    let mut path = if path_file.exists() {
+        keyframes::open_path(&path_file).unwrap()
+    } else {
+        CameraPath::new()
+    };
+    match args.value_of("interpolation") {
+        Some("bezier") => path.interpolation = Interpolation::Bezier,
+        Some(_) => path.interpolation = Interpolation::CatmullRom,
+        None => (),
+    }
+
+    Args {
+        scene: match args.value_of("scene") {
+            Some(file) => scene::open_scene(file).unwrap(),
+            None => scene_from_args(&args),
+        },
+        save_path: PathBuf::from(args.value_of("save").unwrap()),
+        debug_view: args.value_of("debug_view").unwrap().parse().expect("Could not parse debug view"),
+        path: path,
+        path_file: path_file,
+        play: args.is_present("play"),
+        fixed_step: args.value_of("fixed_step").map(|v| v.parse().expect("Could not parse fixed time step")),
+    }
+}
 
+/// Everything a scene file would set, from the command line instead.
+fn scene_from_args(args: &::clap::ArgMatches) -> Scene {
+    let rig = match args.value_of("rig") {
+        Some(path) => rig::open_rig(path).unwrap(),
+        None => Rig::orbit(
+            args.value_of("lights").map(|v| v.parse()).unwrap().expect("Could not parse light count"),
+            get_color(args.values_of("color").unwrap()).expect("Could not parse light color arg"),
+            get_color(args.values_of("ambient").unwrap()).expect("Could not parse ambient color arg"),
+        ),
+    };
+
+    // objects are lined up along the x axis, centered on the origin
+    let dirs: Vec<_> = args.values_of("object").map(|v| v.map(|v| PathBuf::from(v)).collect()).unwrap_or_default();
+    let spacing: f32 = args.value_of("spacing").unwrap().parse().expect("Could not parse object spacing");
+    let normalize = args.value_of("normalize").map(|v| v.parse().expect("Could not parse normalize radius"));
+    let shape = args.value_of("shape").map(|v| v.parse().expect("Could not parse shape"));
+    let count = dirs.len();
+    let objects = dirs.into_iter().enumerate().map(|(i, dir)| SceneObject {
+        position: vec3((i as f32 - (count - 1) as f32 * 0.5) * spacing, 0., 0.),
+        normalize: normalize,
+        shape: shape,
+        .. SceneObject::new(dir)
+    }).collect();
+
+    Scene {
+        objects: objects,
+        board: args.value_of("board").map(|n| {
+            let size = n.parse().expect("Could not parse board size");
+            assert!(size > 0, "The board needs at least one sphere");
+            Board {
+                size: size,
+                .. Board::default()
+            }
+        }),
+        ground: args.value_of("ground").map(|v| v.parse().expect("Could not parse ground height")),
+        camera: None,
+        gamma: 2.2,
+        rig: rig,
+        tonemap: args.value_of("tonemap").unwrap().parse().expect("Could not parse tone mapping operator"),
+        auto_exposure: {
+            let default = AutoExposure::default();
+            let ev_range: Vec<f32> = args.values_of("ev_range")
+                .map(|v| v.map(|v| v.parse().expect("Could not parse EV range")).collect())
+                .unwrap_or(vec![default.min_ev, default.max_ev]);
+
+            AutoExposure {
+                enabled: args.is_present("auto_exposure"),
+                min_ev: ev_range[0],
+                max_ev: ev_range[1],
+                compensation: args.value_of("ev_compensation").unwrap().parse()
+                    .expect("Could not parse EV compensation"),
+                .. default
+            }
+        },
+        bloom: {
+            let intensity: f32 = args.value_of("bloom_intensity").unwrap().parse()
+                .expect("Could not parse bloom intensity");
+            let threshold: f32 = args.value_of("bloom_threshold").unwrap().parse()
+                .expect("Could not parse bloom threshold");
+
+            Bloom {
+                mode: if intensity > 0. { post::BloomMode::On } else { post::BloomMode::Off },
+                intensity: if intensity > 0. { intensity } else { Bloom::default().intensity },
+                radius: args.value_of("bloom_radius").unwrap().parse().expect("Could not parse bloom radius"),
+                threshold: threshold,
+                knee: threshold * 0.5,
+            }
+        },
+        ssao: {
+            let intensity: f32 = args.value_of("ssao_intensity").unwrap().parse()
+                .expect("Could not parse SSAO intensity");
+
+            Ssao {
+                enabled: args.is_present("ssao") && intensity > 0.,
+                radius: args.value_of("ssao_radius").unwrap().parse().expect("Could not parse SSAO radius"),
+                intensity: intensity,
+                .. Ssao::default()
+            }
+        },
+        ssr: {
+            let intensity: f32 = args.value_of("ssr_intensity").unwrap().parse()
+                .expect("Could not parse SSR intensity");
+
+            Ssr {
+                enabled: args.is_present("ssr") && intensity > 0.,
+                intensity: intensity,
+                .. Ssr::default()
+            }
+        },
+        aa: args.value_of("aa").unwrap().parse().expect("Could not parse anti-aliasing mode"),
+        lens: PhysicalCamera {
+            focal_length: args.value_of("focal_length").unwrap().parse().expect("Could not parse focal length"),
+            f_stop: args.value_of("f_stop").unwrap().parse().expect("Could not parse f-stop"),
+            shutter: args.value_of("shutter").unwrap().parse().expect("Could not parse shutter time"),
+            iso: args.value_of("iso").unwrap().parse().expect("Could not parse ISO"),
+            .. PhysicalCamera::default()
+        },
+        dof: Dof {
+            enabled: args.is_present("dof"),
+            focus_dist: args.value_of("focus").unwrap().parse().expect("Could not parse focus distance"),
+            .. Dof::default()
+        },
+        grade: Grade {
+            lift: args.values_of("lift").map(get_rgb).unwrap_or(Grade::default().lift),
+            gamma: args.values_of("grade_gamma").map(get_rgb).unwrap_or(Grade::default().gamma),
+            gain: args.values_of("gain").map(get_rgb).unwrap_or(Grade::default().gain),
+            saturation: args.value_of("saturation").unwrap().parse().expect("Could not parse saturation"),
+            temperature: args.value_of("temperature").unwrap().parse().expect("Could not parse temperature"),
+            tint: args.value_of("tint").unwrap().parse().expect("Could not parse tint"),
+            lut_enabled: args.is_present("lut"),
+        },
+        lut: args.value_of("lut").map(|v| PathBuf::from(v)),
+    }
+}
+
+impl<R: gfx::Resources, C: gfx::CommandBuffer<R>> App<R, C> {
+    /// The arc-ball that sees the current view.
+    fn orbit_view(&self) -> ArcBall<ReverseZ, Deg<f32>> {
+        match self.cam_mode {
+            CameraMode::Fly => self.fly.to_arcball(self.cam.dist),
+            _ => self.cam,
+        }
+    }
+
+    /// Frames an object: the camera orbits its center at a distance where it fills the view, the
+    /// near plane follows its size, and orbiting lights circle it.
+    fn fit_to(&mut self, index: usize, move_camera: bool) {
+        let sphere = {
+            let obj = &self.objects[index];
+            match obj.bounds(&self.graph, obj.node) {
+                Some(b) => b.sphere(),
+                None => return,
+            }
+        };
+
+        if move_camera {
+            // fit the narrower of the two fields of view
+            let tan = (self.cam.projection.fovy / 2.).tan() * self.cam.projection.aspect.min(1.);
+            self.cam.origin = sphere.center;
+            self.cam.dist = sphere.radius * (1. + tan * tan).sqrt() / tan;
+            self.fly = self.cam.to_fly();
+        }
+        self.fly_speed = sphere.radius * 2.;
+
+        // the far plane is at infinity, only the near plane needs to follow
+        self.cam.projection.near = sphere.radius * 0.05;
+
+        for (l, base) in self.lights.iter_mut().zip(&self.rig.lights) {
+            l.fit_orbit(base, Some((sphere.center, sphere.radius)));
+        }
+    }
+
+    /// Describes the closest surface along `ray` for the window title and, unless flying, orbits
+    /// around it from here.
+    fn pick_at(&mut self, ray: Ray) {
+        let mut best: Option<(pick::Hit, String, pick::MaterialSample)> = None;
+        for obj in self.objects.iter().chain(self.ground.iter()) {
+            for (&(part, _), bvh) in obj.parts.iter().zip(&obj.bvhs) {
+                let node = &self.graph.nodes[part];
+                for (m, scale) in obj.placements.iter().zip(&obj.material_scales) {
+                    match bvh.cast(&(m * node.world), &ray) {
+                        Some(h) if best.as_ref().map(|b| h.t < b.0.t).unwrap_or(true) => {
+                            let name = if part == obj.node {
+                                node.name.clone()
+                            } else {
+                                format!("{}/{}", self.graph.nodes[obj.node].name, node.name)
+                            };
+                            let sample = obj.material.sample(h.uv);
+                            best = Some((h, name, pick::MaterialSample {
+                                roughness: sample.roughness * scale[0],
+                                metalness: sample.metalness * scale[1],
+                                .. sample
+                            }));
+                        },
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        let (hit, name, m) = match best {
+            Some(b) => b,
+            None => {
+                self.status = Some("picked nothing".to_string());
+                return
+            },
+        };
+
+        let (p, n) = (hit.position, hit.normal);
+        self.status = Some(format!(
+            "{} at ({:.2}, {:.2}, {:.2}), normal ({:.2}, {:.2}, {:.2}), uv ({:.2}, {:.2}): \
+             albedo #{:02x}{:02x}{:02x}, roughness {:.2}, metalness {:.2}",
+            name, p.x, p.y, p.z, n.x, n.y, n.z, hit.uv.x, hit.uv.y,
+            m.albedo[0], m.albedo[1], m.albedo[2], m.roughness, m.metalness));
+
+        if self.cam_mode != CameraMode::Fly {
+            let eye = self.cam.get_eye();
+            self.cam.look_from(eye, p);
+        }
+    }
+
+    /// Rebuilds every pipeline whose shaders read a file that changed since the last call. A
+    /// pipeline that fails to build keeps the last one that worked.
+    fn reload_shaders<F: gfx::Factory<R>>(&mut self, factory: &mut F) {
+        let changed = self.shader_watch.poll();
+        if changed.is_empty() { return }
+
+        macro_rules! reload {
+            ($app:ident, $factory:ident, $changed:ident; $($name:ident => $pso:ident, $rasterizer:expr;)+) => ($(
+                if shaders::$name::files().iter().any(|f| $changed.contains(f)) {
+                    let shaders = shaders::$name($factory);
+                    match build_pso($factory, stringify!($name), shaders, $rasterizer, define::$name::new()) {
+                        Ok(pso) => {
+                            $app.$pso = pso;
+                            println!("Reloaded {} shaders", stringify!($name));
+                        },
+                        Err(e) => println!("{}\nKeeping the last {} pipeline", e, stringify!($name)),
+                    }
+                }
+            )+)
+        }
+
+        reload! {
+            self, factory, changed;
+            deferred => deferred_pso, fill();
+            pbr => pbr_pso, fill();
+            ldr => ldr_pso, fill();
+            ldr_debug => ldr_debug_pso, fill();
+            shadow => shadow_pso, shadow_rasterizer();
+            log_lum => log_lum_pso, fill();
+            adapt => adapt_pso, fill();
+            ssao => ssao_pso, fill();
+            ssao_blur => ssao_blur_pso, fill();
+            taa => taa_pso, fill();
+            hiz => hiz_pso, fill();
+            ssr => ssr_pso, fill();
+            fxaa => fxaa_pso, fill();
+            bloom_down => bloom_down_pso, fill();
+            bloom_up => bloom_up_pso, fill();
+            dof => dof_pso, fill();
+        }
+    }
 }
 
 impl<R, C> ApplicationBase<R, C> for App<R, C> where
@@ -214,7 +974,14 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
     where F: gfx_app::Factory<R, CommandBuffer=C>,
     {
         // read args
-        let (directories, light_count, mut initial_ambient, mut initial_light) = get_args();
+        let Args { scene, save_path, debug_view, path, path_file, play, fixed_step } = get_args();
+        assert!(!scene.objects.is_empty() || scene.board.is_some(), "The scene has no objects");
+        let rig = scene.rig.clone();
+        let lut = match scene.lut {
+            Some(ref path) => lut::open_cube(path).unwrap(),
+            None => Lut::identity(),
+        };
+
         // inital window size
         let dim = window_targets.color.get_dimensions();
 
@@ -224,22 +991,135 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
             texture::WrapMode::Tile,
         ));
 
-        let objects: Vec<Object<R>> = directories.into_iter().map(|dir| {
+        // stands in for missing optional maps
+        let white = solid_texture(factory, [255, 255, 255, 255]);
+
+        // each object is a node, with a child for every group in its model
+        let mut graph = Graph::new();
+        let mut objects: Vec<Object<R>> = scene.objects.iter().map(|o| {
             use self::format::*;
 
+            let dir = &o.dir;
+            let ao_path = dir.join("ao.png");
+
+            let (mesh, WavefrontMesh { verts, indicies, groups }) = match o.shape {
+                Some(shape) => {
+                    let (verts, indicies) = shape.mesh();
+                    let groups = vec![Group {
+                        name: shape.name().to_string(),
+                        start: 0,
+                        end: indicies.len() as u32,
+                        origin: vec3(0., 0., 0.),
+                        bounds: shapes::bounds(&verts),
+                    }];
+                    (factory.create_vertex_buffer_with_slice(&verts, &indicies[..]), WavefrontMesh {
+                        verts: verts,
+                        indicies: indicies,
+                        groups: groups,
+                    })
+                },
+                None => open_obj(dir.join("model.obj"), factory).unwrap(),
+            };
+            let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let node = graph.add(name, None, o.trs(), None);
+
+            // instances are placed inside the object's own transform, so move them there
+            let root = o.trs().matrix();
+            let placements: Vec<Matrix4<f32>> = if o.instances.is_empty() {
+                vec![Matrix4::identity()]
+            } else {
+                let inv = root.invert().unwrap_or_else(Matrix4::identity);
+                o.instances.iter().map(|i| root * i.trs().matrix() * inv).collect()
+            };
+            let material_scales = vec![[1., 1.]; placements.len()];
+
+            // the groups' bounds together are the model's bounds
+            let model_bounds = groups.iter()
+                .map(|g| Aabb { min: g.bounds.min + g.origin, max: g.bounds.max + g.origin })
+                .fold(None, |acc: Option<Aabb>, b| Some(acc.map(|a| a.union(&b)).unwrap_or(b)));
+
+            let parent = match (o.normalize, model_bounds) {
+                (Some(radius), Some(b)) => {
+                    let sphere = b.sphere();
+                    let scale = radius / sphere.radius;
+                    graph.add("normalize".to_string(), Some(node), Trs {
+                        translation: sphere.center.to_vec() * -scale,
+                        scale: vec3(scale, scale, scale),
+                        .. Trs::identity()
+                    }, None)
+                },
+                _ => node,
+            };
+
+            let mut parts = Vec::with_capacity(groups.len());
+            let mut bvhs = Vec::with_capacity(groups.len());
+            for g in &groups {
+                let local = match o.parts.iter().find(|p| p.name == g.name) {
+                    Some(p) => Trs {
+                        translation: g.origin + p.position,
+                        .. p.trs()
+                    },
+                    None => Trs {
+                        translation: g.origin,
+                        .. Trs::identity()
+                    },
+                };
+
+                let part = graph.add(g.name.clone(), Some(parent), local, Some(g.bounds));
+                parts.push((part, gfx::Slice {
+                    start: g.start,
+                    end: g.end,
+                    instances: Some((placements.len() as u32, 0)),
+                    .. mesh.1.clone()
+                }));
+                bvhs.push(Bvh::new(&verts, &indicies[g.start as usize..g.end as usize]));
+            }
+
+            for p in &o.parts {
+                if !groups.iter().any(|g| g.name == p.name) {
+                    println!("Warning: {} has no part named \"{}\"", dir.display(), p.name);
+                }
+            }
+
+            let albedo = read_image(dir.join("albedo.png"));
+            let metalness = read_image(dir.join("metalness.png"));
+            let roughness = read_image(dir.join("roughness.png"));
+
             Object {
-                mesh: open_obj(dir.join("model.obj"), factory).unwrap(),
+                mesh: mesh,
+                node: node,
+                parts: parts,
+                instances: build_instances(factory, &placements, &material_scales),
+                placements: placements,
+                material_scales: material_scales,
+                bvhs: bvhs,
                 normal: load_image::<_, _, _, (R8_G8_B8_A8, Unorm), _>(factory, dir.join("normal.png")).1,
-                albedo: load_image::<_, _, _, (R8_G8_B8_A8, Unorm), _>(factory, dir.join("albedo.png")).1,
-                metalness: load_image::<_, _, _, (R8_G8_B8_A8, Unorm), _>(factory, dir.join("metalness.png")).1,
-                roughness: load_image::<_, _, _, (R8_G8_B8_A8, Unorm), _>(factory, dir.join("roughness.png")).1,
+                albedo: upload_image::<_, _, _, (R8_G8_B8_A8, Unorm)>(factory, &albedo).1,
+                metalness: upload_image::<_, _, _, (R8_G8_B8_A8, Unorm)>(factory, &metalness).1,
+                roughness: upload_image::<_, _, _, (R8_G8_B8_A8, Unorm)>(factory, &roughness).1,
+                material: Material::new(&albedo, &roughness, &metalness), // picking reads these on the CPU
+                ao: if ao_path.exists() {
+                    load_image::<_, _, _, (R8_G8_B8_A8, Unorm), _>(factory, ao_path).1
+                } else {
+                    white.clone()
+                },
                 sampler: sampler.clone(),
             }
         }).collect();
 
+        if let Some(ref board) = scene.board {
+            objects.push(build_board(factory, &mut graph, board, sampler.clone()));
+        }
+
+        let ground = match scene.ground {
+            Some(height) => Some(build_ground(factory, &mut graph, height, sampler.clone())),
+            None => None,
+        };
+        graph.update();
+
         // create shadow buffer
         let shadow_tex = {
-            let kind = texture::Kind::D2(512, 512, texture::AaMode::Single);
+            let kind = texture::Kind::D2(rig.shadow_size, rig.shadow_size, texture::AaMode::Single);
             let bind = gfx::SHADER_RESOURCE | gfx::DEPTH_STENCIL;
             let ctype = Some(gfx::format::ChannelType::Float);
 
@@ -259,65 +1139,75 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
             (resource, factory.create_sampler(sinfo))
         };
 
+        // the debug view reads raw depth rather than comparing against it
+        let shadow_tex_raw = (shadow_tex_sampler.0.clone(), factory.create_sampler(texture::SamplerInfo::new(
+            texture::FilterMethod::Scale,
+            texture::WrapMode::Clamp,
+        )));
+
         let shadow_depth_target = factory.view_texture_as_depth_stencil(
             &shadow_tex, 0, None,
             texture::DepthStencilFlags::empty()).unwrap();
 
         // create gbuffer
-        let layer_a = build_layer(factory, dim.0, dim.1);
-        let layer_b = build_layer(factory, dim.0, dim.1);
-        let value = build_layer(factory, dim.0, dim.1);
+        let layer_n = build_layer::<_, _, _, define::NormalFormat>(factory, dim.0, dim.1);
+        let layer_albedo = build_layer::<_, _, _, define::AlbedoFormat>(factory, dim.0, dim.1);
+        let layer_material = build_layer::<_, _, _, define::MaterialFormat>(factory, dim.0, dim.1);
+        let layer_v = build_layer(factory, dim.0, dim.1);
+        let luminance = [build_layer(factory, dim.0, dim.1), build_layer(factory, dim.0, dim.1)];
+        let dof_layer = build_layer(factory, dim.0, dim.1);
 
-        let (_, _, depth) = factory.create_depth_stencil(dim.0, dim.1).unwrap();
+        let (_, depth_resource, depth) = factory.create_depth_stencil::<define::SceneDepthFormat>(dim.0, dim.1).unwrap();
 
-        // create pipeline state objects
-        let deferred_pso = {
-            let shaders = shaders::deferred(factory).unwrap();
-            factory.create_pipeline_state(
-                &shaders,
-                gfx::Primitive::TriangleList,
-                gfx::state::Rasterizer::new_fill(),
-                define::deferred::new()
-            ).unwrap()
-        };
+        // create reflection buffers
+        let (hiz, hiz_levels, hiz_chain) = build_hiz(factory, dim.0, dim.1);
 
-        let pbr_pso = {
-            let shaders = shaders::pbr(factory).unwrap();
-            factory.create_pipeline_state(
-                &shaders,
-                gfx::Primitive::TriangleList,
-                gfx::state::Rasterizer::new_fill(),
-                define::pbr::new()
-            ).unwrap()
-        };
+        // create anti-aliasing buffers
+        let ldr_layer = build_layer(factory, dim.0, dim.1);
+        let history = [build_layer(factory, dim.0, dim.1), build_layer(factory, dim.0, dim.1)];
 
-        let ldr_pso = {
-            let shaders = shaders::ldr(factory).unwrap();
-            factory.create_pipeline_state(
-                &shaders,
-                gfx::Primitive::TriangleList,
-                gfx::state::Rasterizer::new_fill(),
-                define::ldr::new()
-            ).unwrap()
-        };
+        let ssao_layers = [build_layer(factory, dim.0, dim.1), build_layer(factory, dim.0, dim.1)];
 
-        let shadow_pso = {
-            use gfx::state::*;
-
-            let shaders = shaders::shadow(factory).unwrap();
-            factory.create_pipeline_state(
-                &shaders,
-                gfx::Primitive::TriangleList,
-                gfx::state::Rasterizer {
-                    front_face: FrontFace::CounterClockwise,
-                    cull_face: CullFace::Front,
-                    method: RasterMethod::Fill,
-                    offset: None,
-                    samples: None,
-                },
-                define::shadow::new()
-            ).unwrap()
-        };
+        // create eye adaptation buffers
+        let log_lum_chain = build_log_lum_chain(factory);
+        let adapted = [build_layer(factory, 1, 1), build_layer(factory, 1, 1)];
+
+        // create bloom buffers
+        let bloom_chain = build_bloom_chain(factory, dim.0, dim.1);
+
+        // create pipeline state objects
+        let shaders = shaders::deferred(factory);
+        let deferred_pso = build_pso(factory, "deferred", shaders, fill(), define::deferred::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::pbr(factory);
+        let pbr_pso = build_pso(factory, "pbr", shaders, fill(), define::pbr::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::ldr(factory);
+        let ldr_pso = build_pso(factory, "ldr", shaders, fill(), define::ldr::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::ldr_debug(factory);
+        let ldr_debug_pso = build_pso(factory, "ldr_debug", shaders, fill(), define::ldr_debug::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::log_lum(factory);
+        let log_lum_pso = build_pso(factory, "log_lum", shaders, fill(), define::log_lum::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::adapt(factory);
+        let adapt_pso = build_pso(factory, "adapt", shaders, fill(), define::adapt::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::ssao(factory);
+        let ssao_pso = build_pso(factory, "ssao", shaders, fill(), define::ssao::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::ssao_blur(factory);
+        let ssao_blur_pso = build_pso(factory, "ssao_blur", shaders, fill(), define::ssao_blur::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::bloom_down(factory);
+        let bloom_down_pso = build_pso(factory, "bloom_down", shaders, fill(), define::bloom_down::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::bloom_up(factory);
+        let bloom_up_pso = build_pso(factory, "bloom_up", shaders, fill(), define::bloom_up::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::taa(factory);
+        let taa_pso = build_pso(factory, "taa", shaders, fill(), define::taa::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::hiz(factory);
+        let hiz_pso = build_pso(factory, "hiz", shaders, fill(), define::hiz::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::ssr(factory);
+        let ssr_pso = build_pso(factory, "ssr", shaders, fill(), define::ssr::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::dof(factory);
+        let dof_pso = build_pso(factory, "dof", shaders, fill(), define::dof::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::fxaa(factory);
+        let fxaa_pso = build_pso(factory, "fxaa", shaders, fill(), define::fxaa::new()).unwrap_or_else(|e| panic!("{}", e));
+        let shaders = shaders::shadow(factory);
+        let shadow_pso = build_pso(factory, "shadow", shaders, shadow_rasterizer(), define::shadow::new()).unwrap_or_else(|e| panic!("{}", e));
 
         // create pipeline data
         let gbuf_sampler = factory.create_sampler(texture::SamplerInfo::new(
@@ -327,10 +1217,18 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
 
         let deferred_data = define::deferred::Data {
             verts: objects[0].mesh.0.clone(),
+            instances: objects[0].instances.clone(),
             transform: factory.create_constant_buffer(1),
-            layer_a: layer_a.target.clone(),
-            layer_b: layer_b.target.clone(),
+            aa: factory.create_constant_buffer(1),
             normal: (objects[0].normal.clone(), sampler.clone()),
+            albedo: (objects[0].albedo.clone(), sampler.clone()),
+            metalness: (objects[0].metalness.clone(), sampler.clone()),
+            roughness: (objects[0].roughness.clone(), sampler.clone()),
+            ao: (objects[0].ao.clone(), sampler.clone()),
+            layer_n: layer_n.target.clone(),
+            layer_albedo: layer_albedo.target.clone(),
+            layer_material: layer_material.target.clone(),
+            layer_v: layer_v.target.clone(),
             depth: depth.clone()
         };
 
@@ -348,154 +1246,624 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
 
         let pbr_data = define::pbr::Data {
             verts: quad.0.clone(),
+            transform: factory.create_constant_buffer(1), // just the camera, shared by every screen pass
             live: factory.create_constant_buffer(1),
             light: factory.create_constant_buffer(1),
-            layer_a: (layer_a.resource.clone(), gbuf_sampler.clone()),
-            layer_b: (layer_b.resource.clone(), gbuf_sampler.clone()),
-            albedo: (objects[0].albedo.clone(), sampler.clone()),
-            metalness: (objects[0].metalness.clone(), sampler.clone()),
-            roughness: (objects[0].roughness.clone(), sampler.clone()),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            layer_n: (layer_n.resource.clone(), gbuf_sampler.clone()),
+            layer_albedo: (layer_albedo.resource.clone(), gbuf_sampler.clone()),
+            layer_material: (layer_material.resource.clone(), gbuf_sampler.clone()),
+            ssao: (ssao_layers[0].resource.clone(), gbuf_sampler.clone()),
             shadow: shadow_tex_sampler,
-            luminance: value.target.clone(),  
+            luminance: luminance[0].target.clone(),  
+        };
+
+        let ssao_data = define::ssao::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            ssao: factory.create_constant_buffer(1),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            layer_n: (layer_n.resource.clone(), gbuf_sampler.clone()),
+            ao: ssao_layers[0].target.clone(),
+        };
+
+        let ssao_blur_data = define::ssao_blur::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            ssao: ssao_data.ssao.clone(),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            source: (ssao_layers[0].resource.clone(), gbuf_sampler.clone()),
+            ao: ssao_layers[1].target.clone(),
+        };
+
+        let log_lum_data = define::log_lum::Data {
+            verts: quad.0.clone(),
+            luminance: (luminance[0].resource.clone(), gbuf_sampler.clone()),
+            reduce: factory.create_constant_buffer(1),
+            below: (log_lum_chain[0].resource.clone(), gbuf_sampler.clone()),
+            log_lum: log_lum_chain[0].target.clone(),
+        };
+
+        let adapt_data = define::adapt::Data {
+            verts: quad.0.clone(),
+            exposure: factory.create_constant_buffer(1),
+            log_lum: (log_lum_chain[log_lum_chain.len() - 1].resource.clone(), gbuf_sampler.clone()),
+            previous: (adapted[0].resource.clone(), gbuf_sampler.clone()),
+            adapted: adapted[1].target.clone(),
+        };
+
+        let linear_sampler = factory.create_sampler(texture::SamplerInfo::new(
+            texture::FilterMethod::Bilinear,
+            texture::WrapMode::Clamp,
+        ));
+
+        let bloom_down_data = define::bloom_down::Data {
+            verts: quad.0.clone(),
+            bloom: factory.create_constant_buffer(1),
+            source: (luminance[0].resource.clone(), linear_sampler.clone()),
+            out: bloom_chain[0].target.clone(),
+        };
+
+        let bloom_up_data = define::bloom_up::Data {
+            verts: quad.0.clone(),
+            bloom: bloom_down_data.bloom.clone(),
+            source: (bloom_chain[1].resource.clone(), linear_sampler.clone()),
+            out: bloom_chain[0].target.clone(),
         };
 
         let ldr_data = define::ldr::Data {
             verts: quad.0.clone(),
             live: pbr_data.live.clone(),
-            luminance: (value.resource.clone(), gbuf_sampler.clone()),
-            color: window_targets.color.clone(),  
+            exposure: adapt_data.exposure.clone(),
+            luminance: (luminance[0].resource.clone(), gbuf_sampler.clone()),
+            adapted: (adapted[1].resource.clone(), gbuf_sampler.clone()),
+            bloom: bloom_down_data.bloom.clone(),
+            bloom_tex: (bloom_chain[0].resource.clone(), linear_sampler.clone()),
+            grade: factory.create_constant_buffer(1),
+            lut: (lut.create_texture(factory), linear_sampler.clone()),
+            color: ldr_layer.target.clone(),
+        };
+
+        let ldr_debug_data = define::ldr_debug::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            live: pbr_data.live.clone(),
+            exposure: adapt_data.exposure.clone(),
+            debug: factory.create_constant_buffer(1),
+            luminance: (luminance[0].resource.clone(), gbuf_sampler.clone()),
+            adapted: (adapted[1].resource.clone(), gbuf_sampler.clone()),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            layer_n: (layer_n.resource.clone(), gbuf_sampler.clone()),
+            layer_albedo: (layer_albedo.resource.clone(), gbuf_sampler.clone()),
+            layer_material: (layer_material.resource.clone(), gbuf_sampler.clone()),
+            velocity: (layer_v.resource.clone(), gbuf_sampler.clone()),
+            shadow: shadow_tex_raw,
+            color: ldr_layer.target.clone(),
+        };
+
+        let taa_data = define::taa::Data {
+            verts: quad.0.clone(),
+            aa: deferred_data.aa.clone(),
+            current: (ldr_layer.resource.clone(), linear_sampler.clone()),
+            history: (history[0].resource.clone(), linear_sampler.clone()),
+            velocity: (layer_v.resource.clone(), gbuf_sampler.clone()),
+            out: history[1].target.clone(),
+        };
+
+        let hiz_data = define::hiz::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            ssr: factory.create_constant_buffer(1),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            below: (hiz_chain[0].resource.clone(), gbuf_sampler.clone()),
+            target: hiz_chain[0].target.clone(),
+            pyramid: hiz_levels[0].clone(),
+        };
+
+        let ssr_data = define::ssr::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            live: pbr_data.live.clone(),
+            ssr: hiz_data.ssr.clone(),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            layer_n: (layer_n.resource.clone(), gbuf_sampler.clone()),
+            layer_albedo: (layer_albedo.resource.clone(), gbuf_sampler.clone()),
+            layer_material: (layer_material.resource.clone(), gbuf_sampler.clone()),
+            velocity: (layer_v.resource.clone(), gbuf_sampler.clone()),
+            hiz: (hiz, gbuf_sampler.clone()),
+            previous: (luminance[1].resource.clone(), linear_sampler.clone()),
+            luminance: luminance[0].target.clone(),
+        };
+
+        let dof_data = define::dof::Data {
+            verts: quad.0.clone(),
+            transform: pbr_data.transform.clone(),
+            dof: factory.create_constant_buffer(1),
+            luminance: (luminance[0].resource.clone(), gbuf_sampler.clone()),
+            depth: (depth_resource.clone(), gbuf_sampler.clone()),
+            out: dof_layer.target.clone(),
+        };
+
+        let fxaa_data = define::fxaa::Data {
+            verts: quad.0.clone(),
+            aa: deferred_data.aa.clone(),
+            source: (ldr_layer.resource.clone(), linear_sampler.clone()),
+            color: window_targets.color.clone(),
         };
 
         let shadow_data = define::shadow::Data {
             verts: objects[0].mesh.0.clone(),
+            instances: objects[0].instances.clone(),
             transform: factory.create_constant_buffer(1),
             depth: shadow_depth_target,
         };
 
-        // create lights
-        initial_ambient[3] *= 1.5 / light_count as f32;
-        initial_light[3] *= 250. / light_count as f32;
-
-        let inital_color = (
-            true,
-            initial_ambient,
-            initial_light,
-        );
+        // without a saved pose the camera gets fitted to the first object below
+        let fit_camera = scene.camera.is_none();
+        let pose = scene.camera.unwrap_or(CameraPose::default());
+        let cam = ArcBall {
+            origin: pose.origin,
+            theta: pose.theta,
+            phi: pose.phi,
+            dist: pose.dist,
+            projection: ReverseZ {
+                fovy: scene.lens.fovy(),
+                aspect: window_targets.aspect_ratio,
+                near: 0.1,
+            },
+        };
+        let prev_view_proj = cam.get_view_proj();
 
-        let lights = (0..light_count)
-            .map(|i| Deg(i as f32 * 360. / light_count as f32))
-            .map(|angle| PointLight {
-                base_angle: angle,
-                ambient: inital_color.1,
-                color: inital_color.2,
-            }).collect();
+        let mut encoder: gfx::Encoder<R, C> = factory.create_encoder();
+        encoder.clear(&adapted[0].target, [0.; 4]);
+        encoder.clear(&adapted[1].target, [0.; 4]);
+        encoder.clear(&luminance[1].target, [0.; 4]);
 
         // put it all together
-        App {
+        let mut app = App {
             mouse_pos: None,
             orbit_diff: (0., 0., 0.),
             left_down: false,
-            cam: ArcBall {
-                origin: Point3::new(0., 0., 0.),
-                theta: Deg(45.),
-                phi: Deg(35.264),
-                dist: 4.,
-                projection: PerspectiveFov {
-                    fovy: Deg(35.).into(),
-                    aspect: window_targets.aspect_ratio, 
-                    near: 0.1, far: 100.
-                },
-            },
+            middle_down: false,
+            pan_diff: (0., 0.),
+            click_pos: None,
+            pick: None,
+            status: None,
+            fly: cam.to_fly(),
+            cam: cam,
+            cam_mode: CameraMode::Orbit,
+            fly_keys: [false; 6],
+            fly_speed: 1.,
+            turntable_speed: 20.,
+            path: path,
+            path_file: path_file,
+            playing: if play { Some(0.) } else { None },
+            fixed_step: fixed_step,
             start_time: Instant::now(),
-            exposure: 0.1,
-            gamma: 2.2,
+            lens: scene.lens,
+            dof: scene.dof,
+            grade: scene.grade,
+            lut: lut,
+            debug_view: debug_view,
+            debug_light: 0,
+            gamma: scene.gamma,
+            tonemap: scene.tonemap,
+            auto_exposure: scene.auto_exposure,
+            bloom: scene.bloom,
+            ssao: scene.ssao,
+            ssr: scene.ssr,
+            aa: scene.aa,
+            frame: 0,
+            prev_view_proj: prev_view_proj,
+            last_time: 0.,
             current: 0,
-            lights: lights,
+            lights: rig.lights.clone(),
+            rig: rig,
             rng: thread_rng(),
-            inital_color: inital_color,
+            random_colors: false,
+            scene: scene,
+            save_path: save_path,
+            culling: true,
+            cull_stats: [CullStats::default(); 2],
 
-            encoder: factory.create_encoder(),
+            encoder: encoder,
+            zero_to_one: false,
 
             objects: objects,
+            ground: ground,
+            graph: graph,
             quad: quad,
 
             deferred_pso: deferred_pso,
             pbr_pso: pbr_pso,
             ldr_pso: ldr_pso,
+            ldr_debug_pso: ldr_debug_pso,
             shadow_pso: shadow_pso,
+            log_lum_pso: log_lum_pso,
+            adapt_pso: adapt_pso,
+            ssao_pso: ssao_pso,
+            ssao_blur_pso: ssao_blur_pso,
+            taa_pso: taa_pso,
+            hiz_pso: hiz_pso,
+            ssr_pso: ssr_pso,
+            fxaa_pso: fxaa_pso,
+            bloom_down_pso: bloom_down_pso,
+            bloom_up_pso: bloom_up_pso,
+            dof_pso: dof_pso,
+            shader_watch: shaders::Watcher::new("shaders", Duration::from_millis(500)),
 
             deferred_data: deferred_data,
             pbr_data: pbr_data,
             ldr_data: ldr_data,
+            ldr_debug_data: ldr_debug_data,
             shadow_data: shadow_data,
-        }
+            log_lum_data: log_lum_data,
+            adapt_data: adapt_data,
+            ssao_data: ssao_data,
+            ssao_blur_data: ssao_blur_data,
+            taa_data: taa_data,
+            hiz_data: hiz_data,
+            ssr_data: ssr_data,
+            fxaa_data: fxaa_data,
+            bloom_down_data: bloom_down_data,
+            bloom_up_data: bloom_up_data,
+            dof_data: dof_data,
+
+            ssao_layers: ssao_layers,
+            luminance: luminance,
+            luminance_index: 1,
+            dof_layer: dof_layer,
+            hiz_levels: hiz_levels,
+            hiz_chain: hiz_chain,
+            ldr_layer: ldr_layer,
+            history: history,
+            history_index: 1,
+            bloom_chain: bloom_chain,
+
+            log_lum_chain: log_lum_chain,
+            adapted: adapted,
+            adapted_index: 1,
+        };
+
+        app.fit_to(0, fit_camera);
+        app
     }
 
     fn render<D>(&mut self, device: &mut D) where
         D: gfx::Device<Resources=R, CommandBuffer=C>
     {
+        self.graph.update();
+
+        let elapsed = match self.fixed_step {
+            Some(step) => self.frame as f64 * step,
+            None => {
+                let elapsed = self.start_time.elapsed();
+                elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9f64
+            },
+        };
+        let delta = elapsed - self.last_time;
+        self.last_time = elapsed;
+
+        // a playing camera path overrides the view, lens and focus
+        if let Some(start) = self.playing {
+            let time = (elapsed - start) as f32;
+            if let Some(key) = self.path.sample(time) {
+                self.cam.look_from(key.eye, key.target);
+                self.fly = self.cam.to_fly();
+                self.lens.set_fovy(key.fov.into());
+                self.dof.focus_point = None;
+                self.dof.focus_dist = key.focus;
+            }
+
+            if time >= self.path.duration() {
+                self.playing = None;
+                println!("Camera path finished");
+            }
+        }
+
         // camera stuff
-        self.cam.theta += Deg(self.orbit_diff.0 * 0.2);
-        self.cam.phi += Deg(self.orbit_diff.1 * 0.2);
-        if self.cam.phi < Deg(-89.) { self.cam.phi = Deg(-89.) }
-        if self.cam.phi > Deg(89.) { self.cam.phi = Deg(89.) }
+        self.cam.projection.fovy = self.lens.fovy();
+        self.fly.projection = self.cam.projection;
+
+        // panning moves the scene along with the mouse, at the orbit distance
+        let (_, h, _, _) = self.ldr_layer.target.get_dimensions();
+        let pan_scale = 2. * self.cam.dist * (self.cam.projection.fovy / 2.).tan() / h as f32;
+        let pan = vec2(-self.pan_diff.0, self.pan_diff.1) * pan_scale;
+
+        match self.cam_mode {
+            _ if self.playing.is_some() => (),
+            CameraMode::Fly => {
+                self.fly.yaw += Deg(self.orbit_diff.0 * 0.2);
+                self.fly.pitch -= Deg(self.orbit_diff.1 * 0.2);
+                if self.fly.pitch < Deg(-89.) { self.fly.pitch = Deg(-89.) }
+                if self.fly.pitch > Deg(89.) { self.fly.pitch = Deg(89.) }
+
+                let k = self.fly_keys;
+                let axis = |pos: bool, neg: bool| pos as i32 as f32 - neg as i32 as f32;
+                let step = self.fly.forward() * axis(k[0], k[1])
+                    + self.fly.right() * axis(k[3], k[2])
+                    + vec3(0., axis(k[4], k[5]), 0.);
+
+                // scrolling changes the speed instead of zooming
+                self.fly_speed *= (self.orbit_diff.2 * 0.1).exp();
+                self.fly.eye += step * self.fly_speed * delta as f32;
+                self.fly.pan(pan);
+            },
+            _ => {
+                if self.cam_mode == CameraMode::Turntable {
+                    self.cam.theta += Deg(self.turntable_speed * delta as f32);
+                }
+
+                self.cam.theta += Deg(self.orbit_diff.0 * 0.2);
+                self.cam.phi += Deg(self.orbit_diff.1 * 0.2);
+                if self.cam.phi < Deg(-89.) { self.cam.phi = Deg(-89.) }
+                if self.cam.phi > Deg(89.) { self.cam.phi = Deg(89.) }
 
-        self.cam.dist *= (self.orbit_diff.2 * -0.1).exp();
+                self.cam.dist *= (self.orbit_diff.2 * -0.1).exp();
+                self.cam.pan(pan);
+            },
+        }
         self.orbit_diff = (0., 0., 0.);
+        self.pan_diff = (0., 0.);
+
+        // TAA renders every frame with a slightly different subpixel offset
+        let jitter = if self.aa == AntiAlias::Taa {
+            let (w, h, _, _) = self.ldr_layer.target.get_dimensions();
+            post::jitter(self.frame, w, h)
+        } else {
+            vec2(0., 0.)
+        };
+        self.frame += 1;
+
+        let view = match self.cam_mode {
+            CameraMode::Fly => self.fly.to_camera(),
+            _ => self.cam.to_camera(),
+        };
+        let camera = view.to_jittered(jitter);
+        let view_proj = view.get_view_proj();
 
-        let camera = self.cam.to_camera();
+        if let Some(ray) = self.pick.take().and_then(|ndc| view.get_ray(ndc)) {
+            self.pick_at(ray);
+        }
+
+        // swap luminance buffers, the previous frame is still needed for reflections
+        let (prev, next) = (self.luminance_index, 1 - self.luminance_index);
+        self.luminance_index = next;
+        self.pbr_data.luminance = self.luminance[next].target.clone();
+        self.ssr_data.luminance = self.luminance[next].target.clone();
+        self.ssr_data.previous.0 = self.luminance[prev].resource.clone();
+        self.dof_data.luminance.0 = self.luminance[next].resource.clone();
 
-        let elapsed = self.start_time.elapsed();
-        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9f64;
+        // everything after depth of field reads the blurred image instead
+        let lum = if self.dof.enabled { &self.dof_layer } else { &self.luminance[next] };
+        self.ldr_data.luminance.0 = lum.resource.clone();
+        self.ldr_debug_data.luminance.0 = lum.resource.clone();
+        self.log_lum_data.luminance.0 = lum.resource.clone();
 
         // clear screen
         self.encoder.clear(&self.pbr_data.luminance, [0.; 4]);
-        self.encoder.clear(&self.deferred_data.layer_a, [0.; 4]);
-        self.encoder.clear(&self.deferred_data.layer_b, [0.; 4]);
-        self.encoder.clear_depth(&self.deferred_data.depth, self.cam.projection.far);
-
-        let model_mat = Matrix4::identity();
-        let obj = &self.objects[self.current];
+        self.encoder.clear(&self.deferred_data.layer_n, [0.; 2]);
+        self.encoder.clear(&self.deferred_data.layer_albedo, [0.; 4]);
+        self.encoder.clear(&self.deferred_data.layer_material, [0.; 4]);
+        self.encoder.clear(&self.deferred_data.layer_v, [0.; 4]);
+        self.encoder.clear_depth(&self.deferred_data.depth, 0.); // reverse-Z
+
+        self.encoder.update_constant_buffer(&self.deferred_data.aa, &define::AaBlock {
+            prev_view_proj: self.prev_view_proj.into(),
+            curr_view_proj: view_proj.into(),
+            jitter: jitter.extend(0.).extend(0.).into(),
+            feedback: 0.9,
+            fxaa: if self.aa == AntiAlias::Fxaa { 1 } else { 0 },
+        });
+        self.prev_view_proj = view_proj;
 
-        self.encoder.update_constant_buffer(&self.deferred_data.transform, &define::TransformBlock {
-            model: model_mat.into(),
+        // a degenerate camera draws nothing sensible either way, it just shouldn't bring the demo down
+        let inv_view_proj = camera.get_inv_view_proj().unwrap_or(Matrix4::identity());
+        self.encoder.update_constant_buffer(&self.pbr_data.transform, &define::TransformBlock {
+            model: Matrix4::identity().into(),
             view: camera.get_view().into(),
             proj: camera.get_proj().into(),
+            inv_view_proj: inv_view_proj.into(),
+            normal: Matrix4::identity().into(),
         });
 
-        self.encoder.draw(&obj.mesh.1, &self.deferred_pso, &self.deferred_data);
+        // every object binds its own mesh and material, every part its own transform
+        let frustum = camera.get_frustum();
+        let mut stats = CullStats::default();
+        for obj in self.objects.iter().chain(self.ground.iter()) {
+            // the whole object first, then each of its parts
+            if self.culling && !obj.visible(&self.graph, obj.node, &frustum) {
+                stats.culled += obj.parts.len();
+                continue;
+            }
+
+            obj.apply_to_data(&mut self.deferred_data, &mut self.shadow_data);
+            for &(node, ref slice) in &obj.parts {
+                if self.culling && !obj.visible(&self.graph, node, &frustum) {
+                    stats.culled += 1;
+                    continue;
+                }
+                stats.drawn += 1;
+
+                self.encoder.update_constant_buffer(&self.deferred_data.transform, &define::TransformBlock {
+                    model: self.graph.nodes[node].world.into(),
+                    view: camera.get_view().into(),
+                    proj: camera::clip_depth(camera.get_proj(), true, self.zero_to_one).into(),
+                    inv_view_proj: inv_view_proj.into(),
+                    normal: graph::normal_matrix(&self.graph.nodes[node].world).into(),
+                });
+                self.encoder.draw(slice, &self.deferred_pso, &self.deferred_data);
+            }
+        }
+        self.cull_stats[0] = stats;
+
+        // ambient occlusion, then a horizontal and vertical blur back into the first layer
+        self.encoder.update_constant_buffer(&self.ssao_data.ssao, &self.ssao.to_block([0., 0.]));
+        self.encoder.draw(&self.quad.1, &self.ssao_pso, &self.ssao_data);
+
+        if self.ssao.enabled {
+            self.ssao_blur_data.source.0 = self.ssao_layers[0].resource.clone();
+            self.ssao_blur_data.ao = self.ssao_layers[1].target.clone();
+            self.encoder.update_constant_buffer(&self.ssao_data.ssao, &self.ssao.to_block([1., 0.]));
+            self.encoder.draw(&self.quad.1, &self.ssao_blur_pso, &self.ssao_blur_data);
+
+            self.ssao_blur_data.source.0 = self.ssao_layers[1].resource.clone();
+            self.ssao_blur_data.ao = self.ssao_layers[0].target.clone();
+            self.encoder.update_constant_buffer(&self.ssao_data.ssao, &self.ssao.to_block([0., 1.]));
+            self.encoder.draw(&self.quad.1, &self.ssao_blur_pso, &self.ssao_blur_data);
+        }
 
         self.encoder.update_constant_buffer(&self.pbr_data.live, &define::LiveBlock {
             eye_pos: camera.get_eye().to_vec().extend(1.).into(),
-            exposure: self.exposure,
+            exposure: self.lens.exposure(),
             gamma: self.gamma,
             time: elapsed as f32,
+            tonemap: self.tonemap.id(),
         });
 
-        let lights = self.lights.iter().map(|l| (l.animate_camera(elapsed as f32), &l.color, &l.ambient));
-        for (ref cam, color, ambient) in lights {
-            self.encoder.update_constant_buffer(&self.shadow_data.transform, &define::TransformBlock {
-                model: model_mat.into(),
-                view: cam.get_view().into(),
-                proj: cam.get_proj().into(),
-            });
-            self.encoder.clear_depth(&self.shadow_data.depth, camera.projection.far);
-            self.encoder.draw(&obj.mesh.1, &self.shadow_pso, &self.shadow_data);
-
-            self.encoder.clear_depth(&self.deferred_data.depth, self.cam.projection.far); // hack around bug
-                                                                                          // TODO: fix bug
+        let mut stats = CullStats::default();
+        for (i, light) in self.lights.iter().enumerate() {
+            if self.debug_view.single_light() && i != self.debug_light { continue }
+
+            let cam = light.shadow_camera(elapsed as f32);
+            if light.shadow.enabled {
+                self.encoder.clear_depth(&self.shadow_data.depth, 1.);
+
+                // nothing outside of the light's frustum can cast a shadow into its shadow map
+                let frustum = cam.get_frustum();
+                let inv_view_proj = cam.get_inv_view_proj().unwrap_or(Matrix4::identity());
+                for obj in self.objects.iter().chain(self.ground.iter()) {
+                    if self.culling && !obj.visible(&self.graph, obj.node, &frustum) {
+                        stats.culled += obj.parts.len();
+                        continue;
+                    }
+
+                    obj.apply_to_data(&mut self.deferred_data, &mut self.shadow_data);
+                    for &(node, ref slice) in &obj.parts {
+                        if self.culling && !obj.visible(&self.graph, node, &frustum) {
+                            stats.culled += 1;
+                            continue;
+                        }
+                        stats.drawn += 1;
+
+                        self.encoder.update_constant_buffer(&self.shadow_data.transform, &define::TransformBlock {
+                            model: self.graph.nodes[node].world.into(),
+                            view: cam.get_view().into(),
+                            proj: camera::clip_depth(cam.get_proj(), false, self.zero_to_one).into(),
+                            inv_view_proj: inv_view_proj.into(),
+                            normal: graph::normal_matrix(&self.graph.nodes[node].world).into(),
+                        });
+                        self.encoder.draw(slice, &self.shadow_pso, &self.shadow_data);
+                    }
+                }
+            }
 
             self.encoder.update_constant_buffer(&self.pbr_data.light, &define::LightBlock {
-                matrix: (cam.get_proj() * cam.get_view()).into(),
+                matrix: cam.get_view_proj().into(),
                 pos: cam.get_eye().to_vec().extend(1.).into(),
-                color: *color,
-                ambient: *ambient,
-
+                dir: light.direction(elapsed as f32).extend(0.).into(),
+                color: light.fitted_color(&self.rig.lights[i]),
+                ambient: light.ambient,
+                cone: light.cone(),
+                shadow: light.shadow_params(),
             });
             self.encoder.draw(&self.quad.1, &self.pbr_pso, &self.pbr_data);
         }
+        self.cull_stats[1] = stats;
+
+        // reflections: build the depth pyramid, then trace against it
+        if self.ssr.enabled {
+            let levels = self.hiz_levels.len();
+            for level in 0..levels {
+                if level > 0 {
+                    self.hiz_data.below.0 = self.hiz_chain[level - 1].resource.clone();
+                }
+                self.hiz_data.target = self.hiz_chain[level].target.clone();
+                self.hiz_data.pyramid = self.hiz_levels[level].clone();
+                self.encoder.update_constant_buffer(&self.hiz_data.ssr, &self.ssr.to_block(level, levels));
+                self.encoder.draw(&self.quad.1, &self.hiz_pso, &self.hiz_data);
+            }
+
+            self.encoder.draw(&self.quad.1, &self.ssr_pso, &self.ssr_data);
+        }
+
+        // depth of field
+        if self.dof.enabled {
+            self.encoder.update_constant_buffer(&self.dof_data.dof, &self.dof.to_block(&self.lens));
+            self.encoder.draw(&self.quad.1, &self.dof_pso, &self.dof_data);
+        }
+
+        // bloom: threshold + downsample, then upsample back up to the largest level
+        if self.bloom.mode != post::BloomMode::Off {
+            let levels = self.bloom_chain.len();
+
+            for i in 0..levels {
+                self.bloom_down_data.source.0 = if i == 0 {
+                    self.ldr_data.luminance.0.clone()
+                } else {
+                    self.bloom_chain[i - 1].resource.clone()
+                };
+                self.bloom_down_data.out = self.bloom_chain[i].target.clone();
+
+                self.encoder.update_constant_buffer(&self.bloom_down_data.bloom, &self.bloom.to_block(i == 0));
+                self.encoder.draw(&self.quad.1, &self.bloom_down_pso, &self.bloom_down_data);
+            }
+
+            for i in (0..levels - 1).rev() {
+                self.bloom_up_data.source.0 = self.bloom_chain[i + 1].resource.clone();
+                self.bloom_up_data.out = self.bloom_chain[i].target.clone();
+                self.encoder.draw(&self.quad.1, &self.bloom_up_pso, &self.bloom_up_data);
+            }
+        } else {
+            self.encoder.update_constant_buffer(&self.bloom_down_data.bloom, &self.bloom.to_block(false));
+        }
+
+        // eye adaptation
+        for i in 0..self.log_lum_chain.len() {
+            if i > 0 {
+                self.log_lum_data.below.0 = self.log_lum_chain[i - 1].resource.clone();
+            }
+            self.log_lum_data.log_lum = self.log_lum_chain[i].target.clone();
+            self.encoder.update_constant_buffer(&self.log_lum_data.reduce, &define::LogLumBlock { level: i as i32 });
+            self.encoder.draw(&self.quad.1, &self.log_lum_pso, &self.log_lum_data);
+        }
+
+        let (prev, next) = (self.adapted_index, 1 - self.adapted_index);
+        self.adapt_data.previous.0 = self.adapted[prev].resource.clone();
+        self.adapt_data.adapted = self.adapted[next].target.clone();
+        self.ldr_data.adapted.0 = self.adapted[next].resource.clone();
+        self.ldr_debug_data.adapted.0 = self.adapted[next].resource.clone();
+        self.adapted_index = next;
+
+        self.encoder.update_constant_buffer(&self.adapt_data.exposure, &self.auto_exposure.to_block(delta as f32));
+        self.encoder.draw(&self.quad.1, &self.adapt_pso, &self.adapt_data);
+
+        if self.debug_view.replaces_ldr() {
+            let clip = match self.lights.get(self.debug_light) {
+                Some(l) => l.shadow_camera(elapsed as f32).get_clip(),
+                None => (0.1, 100.),
+            };
+            self.encoder.update_constant_buffer(&self.ldr_debug_data.debug, &self.debug_view.to_block(clip));
+            self.encoder.draw(&self.quad.1, &self.ldr_debug_pso, &self.ldr_debug_data);
+        } else {
+            self.encoder.update_constant_buffer(&self.ldr_data.grade, &self.grade.to_block(&self.lut));
+            self.encoder.draw(&self.quad.1, &self.ldr_pso, &self.ldr_data);
+        }
+
+        // anti-aliasing
+        if self.aa == AntiAlias::Taa {
+            let (prev, next) = (self.history_index, 1 - self.history_index);
+            self.taa_data.history.0 = self.history[prev].resource.clone();
+            self.taa_data.out = self.history[next].target.clone();
+            self.history_index = next;
+
+            self.encoder.draw(&self.quad.1, &self.taa_pso, &self.taa_data);
+            self.fxaa_data.source.0 = self.history[next].resource.clone();
+        } else {
+            self.fxaa_data.source.0 = self.ldr_layer.resource.clone();
+        }
 
-        self.encoder.draw(&self.quad.1, &self.ldr_pso, &self.ldr_data);
+        self.encoder.draw(&self.quad.1, &self.fxaa_pso, &self.fxaa_data);
 
         // send to GPU
         self.encoder.flush(device);
@@ -513,19 +1881,191 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
             KeyboardInput(state, _, Some(code)) => {
                 use winit::VirtualKeyCode::*;
 
+                // while flying the movement keys only move the camera, and no other binding uses them
+                let fly_key = match code {
+                    W => Some(0), S => Some(1), A => Some(2), D => Some(3), Space => Some(4), LShift => Some(5),
+                    _ => None,
+                };
+                if let (CameraMode::Fly, Some(i)) = (self.cam_mode, fly_key) {
+                    self.fly_keys[i] = state == Pressed;
+                    return;
+                }
+
                 match (state, code) {
-                    (Pressed, Up) => self.exposure *= 1.1,
-                    (Pressed, Down) => self.exposure *= 0.9,
+                    (Pressed, Up) | (Pressed, Down) => {
+                        let step = 2f32.powf(1. / 3.);
+                        self.lens.shutter *= if code == Up { step } else { 1. / step };
+                        println!("Shutter: {:.4}s{}", self.lens.shutter,
+                            if self.auto_exposure.enabled { " (not used while auto exposure is on)" } else { "" });
+                    },
+                    (Pressed, Home) | (Pressed, End) => {
+                        self.auto_exposure.compensation += if code == Home { 0.25 } else { -0.25 };
+                        println!("Exposure compensation: {:+.2} EV{}", self.auto_exposure.compensation,
+                            if self.auto_exposure.enabled { "" } else { " (only used by auto exposure)" });
+                    },
+                    (Pressed, RBracket) => {
+                        self.lens.f_stop = (self.lens.f_stop * 2f32.powf(1. / 6.)).min(32.);
+                        println!("Aperture: f/{:.1}", self.lens.f_stop);
+                    },
+                    (Pressed, LBracket) => {
+                        self.lens.f_stop = (self.lens.f_stop / 2f32.powf(1. / 6.)).max(1.);
+                        println!("Aperture: f/{:.1}", self.lens.f_stop);
+                    },
+                    (Pressed, Equals) => {
+                        self.lens.focal_length = (self.lens.focal_length + 5.).min(300.);
+                        println!("Focal length: {}mm", self.lens.focal_length);
+                    },
+                    (Pressed, Minus) => {
+                        self.lens.focal_length = (self.lens.focal_length - 5.).max(10.);
+                        println!("Focal length: {}mm", self.lens.focal_length);
+                    },
+                    (Pressed, U) => {
+                        self.culling = !self.culling;
+                        println!("Frustum culling: {}", if self.culling { "on" } else { "off" });
+                    },
+                    (Pressed, I) => {
+                        let (main, shadow) = (self.cull_stats[0], self.cull_stats[1]);
+                        println!("Main pass: {} parts drawn, {} culled", main.drawn, main.culled);
+                        println!("Shadow passes: {} parts drawn, {} culled", shadow.drawn, shadow.culled);
+                    },
+                    (Pressed, G) => {
+                        self.grade.lut_enabled = !self.grade.lut_enabled;
+                        println!("LUT: {}", if self.grade.lut_enabled { "on" } else { "off" });
+                    },
+                    (Pressed, Period) => self.grade.saturation *= 1.1,
+                    (Pressed, Comma) => self.grade.saturation *= 0.9,
+                    (Pressed, PageUp) => {
+                        self.grade.temperature = (self.grade.temperature + 0.1).min(1.);
+                        println!("Temperature: {:.1}", self.grade.temperature);
+                    },
+                    (Pressed, PageDown) => {
+                        self.grade.temperature = (self.grade.temperature - 0.1).max(-1.);
+                        println!("Temperature: {:.1}", self.grade.temperature);
+                    },
+                    (Pressed, F1) => {
+                        self.debug_view = self.debug_view.next();
+                        println!("Debug view: {}", self.debug_view.name());
+                    },
+                    (Pressed, F2) if !self.lights.is_empty() => {
+                        self.debug_light = (self.debug_light + 1) % self.lights.len();
+                        println!("Debug light: {}", self.lights[self.debug_light].name);
+                    },
+                    (Pressed, F) => {
+                        self.dof.enabled = !self.dof.enabled;
+                        println!("Depth of field: {}", if self.dof.enabled { "on" } else { "off" });
+                    },
+                    (Pressed, B) => {
+                        self.bloom.next_mode();
+                        println!("Bloom: {:?}", self.bloom.mode);
+                    },
+                    (Pressed, O) => {
+                        self.ssao.enabled = !self.ssao.enabled;
+                        println!("SSAO: {}", if self.ssao.enabled { "on" } else { "off" });
+                    },
+                    (Pressed, X) => {
+                        self.aa = self.aa.next();
+                        println!("Anti-aliasing: {:?}", self.aa);
+                    },
+                    (Pressed, R) => {
+                        self.ssr.enabled = !self.ssr.enabled;
+                        println!("Reflections: {}", if self.ssr.enabled { "on" } else { "off" });
+                    },
+                    (Pressed, E) => {
+                        self.auto_exposure.enabled = !self.auto_exposure.enabled;
+                        println!("Auto exposure: {}", if self.auto_exposure.enabled { "on" } else { "off" });
+                    },
                     (Pressed, Right) => self.gamma *= 1.05,
                     (Pressed, Left) => self.gamma *= 0.95,
+                    (Pressed, T) => {
+                        self.tonemap = self.tonemap.next();
+                        println!("Tone mapping: {}", self.tonemap.name());
+                    },
+                    (Pressed, K) => {
+                        let orbit = self.orbit_view();
+                        let time = match self.path.keys.last() {
+                            Some(k) => k.time + keyframes::KEY_SPACING,
+                            None => 0.,
+                        };
+                        self.path.keys.push(CameraKey {
+                            time: time,
+                            eye: orbit.to_camera().eye,
+                            target: orbit.origin,
+                            fov: self.lens.fovy().into(),
+                            focus: self.dof.focus_dist,
+                        });
+
+                        match keyframes::save_path(&self.path, &self.path_file) {
+                            Ok(()) => println!("Camera key {} at {}s, saved to {}",
+                                self.path.keys.len(), time, self.path_file.display()),
+                            Err(e) => println!("{}", e),
+                        }
+                    },
+                    (Pressed, P) => {
+                        self.playing = match self.playing {
+                            Some(_) => None,
+                            None if self.path.keys.is_empty() => {
+                                println!("No camera keys to play, \"k\" adds one");
+                                None
+                            },
+                            None => Some(self.last_time),
+                        };
+                        println!("Camera path: {}", if self.playing.is_some() { "playing" } else { "stopped" });
+                    },
+                    (Pressed, V) => {
+                        // hand the view over so it doesn't jump
+                        let next = self.cam_mode.next();
+                        match (self.cam_mode, next) {
+                            (_, CameraMode::Fly) => self.fly = self.cam.to_fly(),
+                            (CameraMode::Fly, _) => self.cam = self.fly.to_arcball(self.cam.dist),
+                            _ => (),
+                        }
+                        self.cam_mode = next;
+                        self.fly_keys = [false; 6];
+                        println!("Camera: {}", self.cam_mode.name());
+                    },
+                    (Pressed, F5) => {
+                        let orbit = self.orbit_view();
+                        let scene = Scene {
+                            rig: Rig {
+                                // orbits are saved as the rig had them, they are fitted again on load
+                                lights: self.lights.iter().zip(&self.rig.lights).map(|(l, base)| {
+                                    let mut l = l.clone();
+                                    l.fit_orbit(base, None);
+                                    l
+                                }).collect(),
+                                shadow_size: self.rig.shadow_size,
+                            },
+                            camera: Some(CameraPose {
+                                origin: orbit.origin,
+                                theta: orbit.theta,
+                                phi: orbit.phi,
+                                dist: orbit.dist,
+                            }),
+                            lens: self.lens,
+                            dof: self.dof,
+                            gamma: self.gamma,
+                            tonemap: self.tonemap,
+                            auto_exposure: self.auto_exposure,
+                            bloom: self.bloom,
+                            ssao: self.ssao,
+                            ssr: self.ssr,
+                            aa: self.aa,
+                            grade: self.grade,
+                            .. self.scene.clone()
+                        };
+
+                        match scene::save_scene(&scene, &self.save_path) {
+                            Ok(()) => println!("Saved scene to {}", self.save_path.display()),
+                            Err(e) => println!("{}", e),
+                        }
+                    },
                     (Pressed, M) => {
                         self.current = (self.current + 1) % self.objects.len();
-                        self.objects[self.current]
-                            .apply_to_data(&mut self.deferred_data, &mut self.pbr_data, &mut self.shadow_data);
+                        let current = self.current;
+                        self.fit_to(current, true);
                     },
                     (Pressed, C) => {
-                        let init = self.inital_color;
-                        if init.0 {
+                        if !self.random_colors {
                             let count = self.lights.len() as f32;
 
                             let ambient = vec3(self.rng.next_f32(), self.rng.next_f32(), self.rng.next_f32())
@@ -541,13 +2081,13 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
                                     .into();
                             }
                         } else {
-                            for l in &mut self.lights {
-                                l.ambient = init.1;
-                                l.color = init.2;
+                            for (l, r) in self.lights.iter_mut().zip(&self.rig.lights) {
+                                l.ambient = r.ambient;
+                                l.color = r.color;
                             }
                         }
 
-                        self.inital_color.0 = !init.0;
+                        self.random_colors = !self.random_colors;
                     },
                     _ => ()
                 }
@@ -569,6 +2109,11 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
                     self.orbit_diff.0 += dx as f32;
                     self.orbit_diff.1 += dy as f32;
                 }
+
+                if self.middle_down {
+                    self.pan_diff.0 += dx as f32;
+                    self.pan_diff.1 += dy as f32;
+                }
             },
             MouseLeft => self.mouse_pos = None,
             MouseInput(state, butt) => {
@@ -577,9 +2122,31 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
                 match (state, butt) {
                     (Pressed, Left) => {
                         self.left_down = true;
+                        self.click_pos = self.mouse_pos;
                     },
                     (Released, Left) => {
                         self.left_down = false;
+
+                        // a click without dragging picks whatever is under the cursor
+                        if let (Some((x0, y0)), Some((x, y))) = (self.click_pos.take(), self.mouse_pos) {
+                            if (x - x0).abs() <= 2 && (y - y0).abs() <= 2 {
+                                let (w, h, _, _) = self.ldr_layer.target.get_dimensions();
+                                self.pick = Some(vec2(x as f32 / w as f32 * 2. - 1., 1. - y as f32 / h as f32 * 2.));
+                            }
+                        }
+                    },
+                    (Pressed, Middle) => {
+                        self.middle_down = true;
+                    },
+                    (Released, Middle) => {
+                        self.middle_down = false;
+                    },
+                    (Pressed, Right) => {
+                        // the dof pass reads the focus distance from the gbuffer at this point
+                        if let Some((x, y)) = self.mouse_pos {
+                            let (w, h, _, _) = self.ldr_layer.target.get_dimensions();
+                            self.dof.focus_point = Some((x as f32 / w as f32, 1. - y as f32 / h as f32));
+                        }
                     },
                     _ => (),
 
@@ -597,22 +2164,89 @@ impl<R, C> ApplicationBase<R, C> for App<R, C> where
     {
         let (w, h, _, _) = window_targets.color.get_dimensions();
 
-        let layer_a = build_layer(factory, w, h);
-        let layer_b = build_layer(factory, w, h);
-        let value = build_layer(factory, w, h);
-
-        self.deferred_data.layer_a = layer_a.target.clone();
-        self.deferred_data.layer_b = layer_b.target.clone();
-        self.pbr_data.luminance = value.target.clone();
-        self.ldr_data.color = window_targets.color.clone();
+        let layer_n = build_layer::<_, _, _, define::NormalFormat>(factory, w, h);
+        let layer_albedo = build_layer::<_, _, _, define::AlbedoFormat>(factory, w, h);
+        let layer_material = build_layer::<_, _, _, define::MaterialFormat>(factory, w, h);
+        let layer_v = build_layer(factory, w, h);
+        let (_, depth_resource, depth) = factory.create_depth_stencil::<define::SceneDepthFormat>(w, h).unwrap();
 
-        let (_, _, depth) = factory.create_depth_stencil(w, h).unwrap();
+        self.deferred_data.layer_n = layer_n.target.clone();
+        self.deferred_data.layer_albedo = layer_albedo.target.clone();
+        self.deferred_data.layer_material = layer_material.target.clone();
+        self.deferred_data.layer_v = layer_v.target.clone();
         self.deferred_data.depth = depth;
-
-        self.pbr_data.layer_a.0 = layer_a.resource.clone();
-        self.pbr_data.layer_b.0 = layer_b.resource.clone();
-        self.ldr_data.luminance.0 = value.resource.clone();
+        self.luminance = [build_layer(factory, w, h), build_layer(factory, w, h)];
+        self.dof_layer = build_layer(factory, w, h);
+        self.dof_data.out = self.dof_layer.target.clone();
+        self.dof_data.depth.0 = depth_resource.clone();
+
+        self.ldr_layer = build_layer(factory, w, h);
+        self.history = [build_layer(factory, w, h), build_layer(factory, w, h)];
+        self.ldr_data.color = self.ldr_layer.target.clone();
+        self.ldr_debug_data.color = self.ldr_layer.target.clone();
+        self.ldr_debug_data.depth.0 = depth_resource.clone();
+        self.ldr_debug_data.layer_n.0 = layer_n.resource.clone();
+        self.ldr_debug_data.layer_albedo.0 = layer_albedo.resource.clone();
+        self.ldr_debug_data.layer_material.0 = layer_material.resource.clone();
+        self.ldr_debug_data.velocity.0 = layer_v.resource.clone();
+        self.taa_data.current.0 = self.ldr_layer.resource.clone();
+        self.taa_data.velocity.0 = layer_v.resource.clone();
+        self.fxaa_data.color = window_targets.color.clone();
+
+        self.pbr_data.depth.0 = depth_resource.clone();
+        self.pbr_data.layer_n.0 = layer_n.resource.clone();
+        self.pbr_data.layer_albedo.0 = layer_albedo.resource.clone();
+        self.pbr_data.layer_material.0 = layer_material.resource.clone();
+
+        let (hiz, hiz_levels, hiz_chain) = build_hiz(factory, w, h);
+        self.hiz_levels = hiz_levels;
+        self.hiz_chain = hiz_chain;
+        self.hiz_data.depth.0 = depth_resource.clone();
+        self.ssr_data.hiz.0 = hiz;
+        self.ssr_data.depth.0 = depth_resource.clone();
+        self.ssr_data.layer_n.0 = layer_n.resource.clone();
+        self.ssr_data.layer_albedo.0 = layer_albedo.resource.clone();
+        self.ssr_data.layer_material.0 = layer_material.resource.clone();
+        self.ssr_data.velocity.0 = layer_v.resource.clone();
+
+        self.ssao_layers = [build_layer(factory, w, h), build_layer(factory, w, h)];
+        self.ssao_data.ao = self.ssao_layers[0].target.clone();
+        self.ssao_data.depth.0 = depth_resource.clone();
+        self.ssao_data.layer_n.0 = layer_n.resource.clone();
+        self.ssao_blur_data.depth.0 = depth_resource;
+        self.pbr_data.ssao.0 = self.ssao_layers[0].resource.clone();
+
+        self.bloom_chain = build_bloom_chain(factory, w, h);
+        self.ldr_data.bloom_tex.0 = self.bloom_chain[0].resource.clone();
 
         self.cam.projection.aspect = window_targets.aspect_ratio;
     }
-}
\ No newline at end of file
+}
+
+/// What the main loop in main.rs gives the app besides `ApplicationBase`, whose `render` doesn't
+/// get the factory.
+pub trait Hooks<R: gfx::Resources, C: gfx::CommandBuffer<R>>: ApplicationBase<R, C> {
+    /// Called before every frame.
+    fn update<F>(&mut self, factory: &mut F) where F: gfx_app::Factory<R, CommandBuffer = C>;
+    /// Text for the window title, when it has changed.
+    fn status(&mut self) -> Option<String>;
+    /// Called once after `new`, with whether clip space depth goes from 0 to 1 instead of -1 to 1.
+    fn set_zero_to_one(&mut self, zero_to_one: bool);
+}
+
+impl<R, C> Hooks<R, C> for App<R, C> where
+    R: gfx::Resources + 'static,
+    C: gfx::CommandBuffer<R> + Send + 'static,
+{
+    fn update<F>(&mut self, factory: &mut F) where F: gfx_app::Factory<R, CommandBuffer = C> {
+        self.reload_shaders(factory);
+    }
+
+    fn status(&mut self) -> Option<String> {
+        self.status.take()
+    }
+
+    fn set_zero_to_one(&mut self, zero_to_one: bool) {
+        self.zero_to_one = zero_to_one;
+    }
+}
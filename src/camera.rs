@@ -1,13 +1,101 @@
 #![allow(dead_code)]
 
 use cgmath::prelude::*;
-use cgmath::{Perspective, PerspectiveFov, Ortho, Rad, Matrix4, Point3, Vector3, Angle, vec3};
+use cgmath::{Perspective, PerspectiveFov, Ortho, Rad, Matrix4, Point3, Vector2, Vector3, Vector4, Angle, vec3};
+
+/// A half line from `origin` along `dir`, which is a unit vector unless the ray was transformed.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub dir: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.dir * t
+    }
+}
+
+/// Points where `normal.dot(p) + dist >= 0` are on the inside.
+#[derive(Debug, Clone, Copy)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub dist: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Plane {
+        // an infinite far plane comes out with no normal and contains everything
+        let len = row.truncate().magnitude();
+        let len = if len > 0. { len } else { 1. };
+        Plane {
+            normal: row.truncate() / len,
+            dist: row.w / len,
+        }
+    }
+
+    /// Signed distance, positive on the inside.
+    pub fn distance(&self, p: Point3<f32>) -> f32 {
+        self.normal.dot(p.to_vec()) + self.dist
+    }
+}
 
 pub trait Camera {
     fn get_eye(&self) -> Point3<f32>;
     fn get_view(&self) -> Matrix4<f32>;
     fn get_proj(&self) -> Matrix4<f32>;
     fn get_clip(&self) -> (f32, f32);
+
+    fn get_view_proj(&self) -> Matrix4<f32> {
+        self.get_proj() * self.get_view()
+    }
+
+    /// None if the camera is degenerate, like a zero sized view or projection.
+    fn get_inv_view_proj(&self) -> Option<Matrix4<f32>> {
+        self.get_view_proj().invert()
+    }
+
+    /// The world position of a point in normalized device coordinates (x and y from -1 to 1, z in
+    /// the projection's own depth range).
+    fn unproject(&self, ndc: Point3<f32>) -> Option<Point3<f32>> {
+        self.get_inv_view_proj().map(|inv| inv.transform_point(ndc))
+    }
+
+    /// The ray through a point on the screen in normalized device coordinates (y up), starting in
+    /// the plane of the eye. Works the same for regular, reverse-Z and orthographic projections.
+    fn get_ray(&self, ndc: Vector2<f32>) -> Option<Ray> {
+        let inv = match self.get_inv_view_proj() {
+            Some(inv) => inv,
+            None => return None,
+        };
+        // reverse-Z puts infinity at 0, so stay clear of it
+        let a = inv.transform_point(Point3::new(ndc.x, ndc.y, 0.5));
+        let b = inv.transform_point(Point3::new(ndc.x, ndc.y, 1.));
+
+        // z = 1 is the near plane with reverse-Z, so point the ray away from the eye explicitly
+        let forward = -self.get_view().row(2).truncate().normalize();
+        let dir = (b - a).normalize();
+        let dir = if dir.dot(forward) < 0. { -dir } else { dir };
+
+        let eye = self.get_eye();
+        Some(Ray {
+            origin: a + dir * -((a - eye).dot(forward) / dir.dot(forward)),
+            dir: dir,
+        })
+    }
+
+    /// Left, right, bottom, top, then the two depth planes (in that order for a regular
+    /// projection, swapped for reverse-Z), all facing inwards. With reverse-Z's 0 to 1 depth the
+    /// far one ends up just behind the eye, so it keeps everything the near plane keeps.
+    fn get_frustum(&self) -> [Plane; 6] {
+        let m = self.get_view_proj();
+        let (x, y, z, w) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        [
+            Plane::from_row(w + x), Plane::from_row(w - x),
+            Plane::from_row(w + y), Plane::from_row(w - y),
+            Plane::from_row(w + z), Plane::from_row(w - z),
+        ]
+    }
 }
 
 pub trait Projection: Clone + Copy {
@@ -61,6 +149,56 @@ impl Projection for PerspectiveFov<f32> {
     }
 }
 
+/// A perspective with the far plane at infinity and the depth range flipped, so the near plane
+/// lands on 1 and infinity on 0; depth tests have to use `GreaterEqual` and clears have to use 0.
+///
+/// Clip space depth goes from 0 to 1, like `glClipControl(GL_LOWER_LEFT, GL_ZERO_TO_ONE)` expects.
+/// Without that, draw with `clip_depth` to get GL's -1 to 1, which stores the same depth but
+/// loses the float precision far away that reverse-Z is for.
+#[derive(Debug, Clone, Copy)]
+pub struct ReverseZ {
+    pub fovy: Rad<f32>,
+    pub aspect: f32,
+    pub near: f32,
+}
+
+impl Projection for ReverseZ {
+    fn matrix(self) -> Matrix4<f32> {
+        let f = Rad::cot(self.fovy / 2.);
+
+        // clip z is the near distance and w the distance, so depth ends up as near / distance
+        Matrix4::new(
+            f / self.aspect, 0., 0., 0.,
+            0., f, 0., 0.,
+            0., 0., 0., -1.,
+            0., 0., self.near, 0.)
+    }
+
+    fn clip(&self) -> (f32, f32) {
+        (self.near, ::std::f32::INFINITY)
+    }
+}
+
+/// Converts `proj` from clip space depth in 0 to 1 (`ReverseZ`) or -1 to 1 (every other
+/// projection) to the range the device clips with. The stored depth is the same either way.
+pub fn clip_depth(proj: Matrix4<f32>, from_zero_to_one: bool, to_zero_to_one: bool) -> Matrix4<f32> {
+    match (from_zero_to_one, to_zero_to_one) {
+        // z' = (z + w) / 2
+        (false, true) => Matrix4::new(
+            1., 0., 0., 0.,
+            0., 1., 0., 0.,
+            0., 0., 0.5, 0.,
+            0., 0., 0.5, 1.) * proj,
+        // z' = 2z - w
+        (true, false) => Matrix4::new(
+            1., 0., 0., 0.,
+            0., 1., 0., 0.,
+            0., 0., 2., 0.,
+            0., 0., -1., 1.) * proj,
+        _ => proj,
+    }
+}
+
 impl Projection for Ortho<f32> {
     fn matrix(self) -> Matrix4<f32> {
         self.into()
@@ -70,6 +208,23 @@ impl Projection for Ortho<f32> {
     }
 }
 
+/// Shifts another projection by a fraction of a pixel, in normalized device coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct Jittered<P: Projection> {
+    pub inner: P,
+    pub offset: Vector2<f32>,
+}
+
+impl<P: Projection> Projection for Jittered<P> {
+    fn matrix(self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.offset.extend(0.)) * self.inner.matrix()
+    }
+
+    fn clip(&self) -> (f32, f32) {
+        self.inner.clip()
+    }
+}
+
 pub struct BasicCamera<P: Projection> {
     pub eye: Point3<f32>,
     pub target: Point3<f32>,
@@ -115,6 +270,25 @@ impl<P: Projection> BasicCamera<P> {
     pub fn to_direct(&self) -> DirectCamera<P> {
         new_direct(self.get_view(), self.projection)
     }
+
+    pub fn to_jittered(&self, offset: Vector2<f32>) -> BasicCamera<Jittered<P>> {
+        BasicCamera {
+            eye: self.eye,
+            target: self.target,
+            up: self.up,
+            projection: Jittered {
+                inner: self.projection,
+                offset: offset,
+            },
+        }
+    }
+
+    /// World space right and up directions of the screen.
+    pub fn view_plane(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let forward = (self.target - self.eye).normalize();
+        let right = forward.cross(self.up).normalize();
+        (right, right.cross(forward))
+    }
 }
 
 impl<P: Projection> Camera for BasicCamera<P> {
@@ -180,6 +354,7 @@ pub fn new_direct_custom(eye: Matrix4<f32>,
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct ArcBall<P: Projection, A: Angle> {
     pub origin: Point3<f32>,
     pub theta: A,
@@ -200,4 +375,324 @@ impl<P: Projection, A: Angle<Unitless=f32>> ArcBall<P, A>
             projection: self.projection,
         }
     }
-}
\ No newline at end of file
+
+    /// The fly camera standing where this one is, looking at the origin.
+    pub fn to_fly(&self) -> FlyCamera<P, A> {
+        FlyCamera {
+            eye: self.to_camera().eye,
+            yaw: self.theta + A::turn_div_2(),
+            pitch: -self.phi,
+            projection: self.projection,
+        }
+    }
+
+    /// Moves the origin along the view plane, `offset` is in world units.
+    pub fn pan(&mut self, offset: Vector2<f32>) {
+        let (right, up) = self.to_camera().view_plane();
+        self.origin += right * offset.x + up * offset.y;
+    }
+
+    /// Orbits `target` from wherever puts the eye at `eye`.
+    pub fn look_from(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+        let offset = eye - target;
+        self.dist = offset.magnitude();
+        let dir = offset / self.dist;
+
+        self.origin = target;
+        self.theta = A::atan2(dir.z, dir.x);
+        self.phi = A::asin(dir.y);
+    }
+}
+
+impl<P: Projection, A: Angle<Unitless=f32>> Camera for ArcBall<P, A> {
+    fn get_eye(&self) -> Point3<f32> {
+        self.to_camera().eye
+    }
+
+    fn get_view(&self) -> Matrix4<f32> {
+        self.to_camera().get_view()
+    }
+
+    fn get_proj(&self) -> Matrix4<f32> {
+        self.projection.matrix()
+    }
+
+    fn get_clip(&self) -> (f32, f32) {
+        self.projection.clip()
+    }
+}
+
+/// A first person camera. Yaw turns from +x towards +z, pitch is up from the horizon.
+#[derive(Debug, Clone, Copy)]
+pub struct FlyCamera<P: Projection, A: Angle> {
+    pub eye: Point3<f32>,
+    pub yaw: A,
+    pub pitch: A,
+    pub projection: P,
+}
+
+impl<P: Projection, A: Angle<Unitless=f32>> FlyCamera<P, A> {
+    pub fn forward(&self) -> Vector3<f32> {
+        let phicos = self.pitch.cos();
+        vec3(self.yaw.cos() * phicos, self.pitch.sin(), self.yaw.sin() * phicos)
+    }
+
+    /// Horizontal, even when looking up or down.
+    pub fn right(&self) -> Vector3<f32> {
+        vec3(-self.yaw.sin(), 0., self.yaw.cos())
+    }
+
+    pub fn to_camera(&self) -> BasicCamera<P> {
+        BasicCamera {
+            eye: self.eye,
+            target: self.eye + self.forward(),
+            up: vec3(0., 1., 0.),
+            projection: self.projection,
+        }
+    }
+
+    /// The arc-ball that sees the same view, orbiting the point `dist` ahead.
+    pub fn to_arcball(&self, dist: f32) -> ArcBall<P, A> {
+        ArcBall {
+            origin: self.eye + self.forward() * dist,
+            theta: self.yaw + A::turn_div_2(),
+            phi: -self.pitch,
+            dist: dist,
+            projection: self.projection,
+        }
+    }
+
+    /// Moves the eye along the view plane, `offset` is in world units.
+    pub fn pan(&mut self, offset: Vector2<f32>) {
+        let (right, up) = self.to_camera().view_plane();
+        self.eye += right * offset.x + up * offset.y;
+    }
+}
+
+/// How the mouse and keyboard move the view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    Turntable, // an orbit that keeps turning on its own
+    Fly,
+}
+
+impl CameraMode {
+    pub fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Orbit => CameraMode::Turntable,
+            CameraMode::Turntable => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Orbit,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CameraMode::Orbit => "orbit",
+            CameraMode::Turntable => "turntable",
+            CameraMode::Fly => "fly",
+        }
+    }
+}
+
+/// Physical lens and sensor settings. Lengths are in millimeters, shutter is in seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalCamera {
+    pub focal_length: f32,
+    pub sensor: (f32, f32),
+    pub f_stop: f32,
+    pub shutter: f32,
+    pub iso: f32,
+}
+
+impl PhysicalCamera {
+    /// Vertical field of view of the lens on this sensor.
+    pub fn fovy(&self) -> Rad<f32> {
+        Rad(2. * (self.sensor.1 / (2. * self.focal_length)).atan())
+    }
+
+    /// Picks the focal length that gives this vertical field of view.
+    pub fn set_fovy(&mut self, fovy: Rad<f32>) {
+        self.focal_length = self.sensor.1 / (2. * (fovy.0 / 2.).tan());
+    }
+
+    /// Exposure value at ISO 100.
+    pub fn ev100(&self) -> f32 {
+        (self.f_stop * self.f_stop / self.shutter * 100. / self.iso).log2()
+    }
+
+    /// Multiplier from scene luminance to sensor exposure (the saturation based model).
+    pub fn exposure(&self) -> f32 {
+        1. / (1.2 * 2f32.powf(self.ev100()))
+    }
+
+    /// Diameter of the aperture in millimeters.
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_stop
+    }
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> PhysicalCamera {
+        PhysicalCamera {
+            focal_length: 38.,
+            sensor: (36., 24.),
+            f_stop: 2.8,
+            shutter: 0.25,
+            iso: 400.,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use cgmath::prelude::*;
+    use cgmath::{PerspectiveFov, Ortho, Rad, Deg, Matrix4, Point3, Vector3, vec2, vec3};
+
+    use super::*;
+
+    fn near(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    fn perspective() -> BasicCamera<PerspectiveFov<f32>> {
+        BasicCamera {
+            eye: Point3::new(3., 2., 5.),
+            target: Point3::new(0., 0.5, 0.),
+            up: vec3(0., 1., 0.),
+            projection: PerspectiveFov { fovy: Deg(60.).into(), aspect: 1.5, near: 0.1, far: 100. },
+        }
+    }
+
+    fn arcball() -> ArcBall<ReverseZ, Deg<f32>> {
+        ArcBall {
+            origin: Point3::new(1., 0., -1.),
+            theta: Deg(30.),
+            phi: Deg(20.),
+            dist: 6.,
+            projection: ReverseZ { fovy: Deg(45.).into(), aspect: 16. / 9., near: 0.1 },
+        }
+    }
+
+    // a camera at the origin looking down -z, so its frustum planes are easy to write down
+    fn down_z<P: Projection>(projection: P) -> BasicCamera<P> {
+        BasicCamera {
+            eye: Point3::new(0., 0., 0.),
+            target: Point3::new(0., 0., -1.),
+            up: vec3(0., 1., 0.),
+            projection: projection,
+        }
+    }
+
+    fn check_round_trip<C: Camera>(camera: &C) {
+        let inv = camera.get_inv_view_proj().unwrap();
+        let identity = inv * camera.get_view_proj();
+        for i in 0..4 {
+            assert!(near((identity[i] - Matrix4::identity()[i]).truncate(), vec3(0., 0., 0.)));
+        }
+
+        for &p in &[Point3::new(0., 0.5, 0.), Point3::new(1., -0.5, 0.25), Point3::new(-0.5, 1., 1.5)] {
+            let ndc = camera.get_view_proj().transform_point(p);
+            assert!(near(camera.unproject(ndc).unwrap().to_vec(), p.to_vec()), "{:?} came back wrong", p);
+        }
+    }
+
+    fn check_plane(plane: &Plane, normal: Vector3<f32>, dist: f32) {
+        assert!(near(plane.normal, normal) && (plane.dist - dist).abs() < 1e-4,
+            "{:?} should be {:?}, {}", plane, normal, dist);
+    }
+
+    #[test]
+    fn round_trip() {
+        check_round_trip(&perspective());
+        check_round_trip(&perspective().to_direct());
+        check_round_trip(&arcball());
+        check_round_trip(&arcball().to_camera().to_direct());
+
+        let ortho = new_orthographic(Point3::new(0., 4., 4.), Point3::new(0., 0., 0.), vec3(0., 1., 0.),
+            -2., 2., 1., -1., 0.5, 20.);
+        check_round_trip(&ortho);
+        check_round_trip(&ortho.to_direct());
+    }
+
+    #[test]
+    fn degenerate() {
+        let camera = new_direct(Matrix4::from_scale(0.), perspective().projection);
+        assert!(camera.get_inv_view_proj().is_none());
+        assert!(camera.unproject(Point3::new(0., 0., 0.)).is_none());
+        assert!(camera.get_ray(vec2(0., 0.)).is_none());
+    }
+
+    #[test]
+    fn perspective_frustum() {
+        let camera = down_z(PerspectiveFov { fovy: Rad::turn_div_4(), aspect: 1., near: 1., far: 10. });
+        let planes = camera.get_frustum();
+        let s = 0.5f32.sqrt();
+
+        check_plane(&planes[0], vec3(s, 0., -s), 0.);
+        check_plane(&planes[1], vec3(-s, 0., -s), 0.);
+        check_plane(&planes[2], vec3(0., s, -s), 0.);
+        check_plane(&planes[3], vec3(0., -s, -s), 0.);
+        check_plane(&planes[4], vec3(0., 0., -1.), -1.);
+        check_plane(&planes[5], vec3(0., 0., 1.), 10.);
+    }
+
+    #[test]
+    fn reverse_z_frustum() {
+        let camera = down_z(ReverseZ { fovy: Rad::turn_div_4(), aspect: 1., near: 1. });
+        let planes = camera.get_frustum();
+
+        check_plane(&planes[4], vec3(0., 0., -1.), 1.);
+        check_plane(&planes[5], vec3(0., 0., -1.), -1.);
+    }
+
+    #[test]
+    fn clip_depth_ranges() {
+        let reverse = ReverseZ { fovy: Rad::turn_div_4(), aspect: 1., near: 1. }.matrix();
+        let regular = PerspectiveFov { fovy: Rad::turn_div_4(), aspect: 1., near: 1., far: 10. };
+        let regular = regular.to_perspective().into();
+
+        // the stored depth doesn't depend on the clip range, only on the projection
+        let stored = |proj: Matrix4<f32>, zero_to_one: bool, z: f32| {
+            let ndc = proj.transform_point(Point3::new(0., 0., z)).z;
+            if zero_to_one { ndc } else { ndc * 0.5 + 0.5 }
+        };
+        for &z in &[-1., -2., -10.] {
+            let d = stored(reverse, true, z);
+            assert!((d - 1. / -z).abs() < 1e-6);
+            assert!((stored(clip_depth(reverse, true, false), false, z) - d).abs() < 1e-6);
+
+            let d = stored(regular, false, z);
+            assert!((stored(clip_depth(regular, false, true), true, z) - d).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn ortho_frustum() {
+        let camera = down_z(Ortho { left: -2., right: 2., bottom: -1., top: 1., near: 1., far: 5. });
+        let planes = camera.get_frustum();
+
+        check_plane(&planes[0], vec3(1., 0., 0.), 2.);
+        check_plane(&planes[1], vec3(-1., 0., 0.), 2.);
+        check_plane(&planes[2], vec3(0., 1., 0.), 1.);
+        check_plane(&planes[3], vec3(0., -1., 0.), 1.);
+        check_plane(&planes[4], vec3(0., 0., -1.), -1.);
+        check_plane(&planes[5], vec3(0., 0., 1.), 5.);
+    }
+
+    #[test]
+    fn center_ray() {
+        let check = |ray: Ray, eye: Point3<f32>, target: Point3<f32>| {
+            assert!(near(ray.dir, (target - eye).normalize()), "{:?} doesn't look at {:?}", ray, target);
+            assert!(near(ray.origin.to_vec(), eye.to_vec()), "{:?} doesn't start at {:?}", ray, eye);
+        };
+
+        let camera = perspective();
+        check(camera.get_ray(vec2(0., 0.)).unwrap(), camera.eye, camera.target);
+
+        let camera = arcball();
+        check(camera.get_ray(vec2(0., 0.)).unwrap(), camera.get_eye(), camera.origin);
+
+        let camera = down_z(Ortho { left: -2., right: 2., bottom: -1., top: 1., near: 1., far: 5. });
+        check(camera.get_ray(vec2(0., 0.)).unwrap(), camera.eye, camera.target);
+    }
+}
@@ -3,13 +3,25 @@
 use gfx;
 use gfx::format;
 
-pub use gfx_app::{ColorFormat, DepthFormat};
+pub use gfx_app::ColorFormat;
 
 pub type LayerFormat = [f32; 4];
+pub type NormalFormat = (format::R16_G16, format::Float);
+pub type AlbedoFormat = (format::R8_G8_B8_A8, format::Unorm);
+pub type MaterialFormat = (format::R8_G8_B8_A8, format::Unorm);
+pub type SceneDepthFormat = (format::D32, format::Float);
 pub type PbrTex = [f32; 4];
 pub type ShadowDepthFormat = (format::D32, format::Float);
 pub type VertexSlice<R, V> = (gfx::handle::Buffer<R, V>, gfx::Slice<R>);
 
+/// ">=" comparison with writable depth, for the reverse-Z scene depth (closer is larger). Only the
+/// deferred pass uses it, and it clears the depth to 0 first. Shadow maps keep the regular
+/// `LESS_EQUAL_WRITE`.
+pub const GREATER_EQUAL_WRITE: gfx::state::Depth = gfx::state::Depth {
+    fun: gfx::state::Comparison::GreaterEqual,
+    write: true,
+};
+
 gfx_defines!{
     #[derive(PartialEq)]
     vertex CtrPoint {
@@ -49,17 +61,35 @@ gfx_defines!{
         a_btn: [f32; 3] = "a_btn",
     }
 
+    // the columns of a matrix applied on top of the model matrix, one per instance,
+    // and multipliers for the roughness and metalness maps
+    vertex Instance {
+        a_inst0: [f32; 4] = "a_inst0",
+        a_inst1: [f32; 4] = "a_inst1",
+        a_inst2: [f32; 4] = "a_inst2",
+        a_inst3: [f32; 4] = "a_inst3",
+        a_inst_nor0: [f32; 3] = "a_inst_nor0", // columns of the normal matrix
+        a_inst_nor1: [f32; 3] = "a_inst_nor1",
+        a_inst_nor2: [f32; 3] = "a_inst_nor2",
+        a_inst_material: [f32; 2] = "a_inst_material",
+    }
+
     constant TransformBlock {
         model: [[f32; 4]; 4] = "model",
         view: [[f32; 4]; 4] = "view",
         proj: [[f32; 4]; 4] = "proj",
+        inv_view_proj: [[f32; 4]; 4] = "inv_view_proj",
+        normal: [[f32; 4]; 4] = "normal_matrix", // of the model, only read by transform.v.glsl
     }
 
     constant LightBlock {
         matrix: [[f32; 4]; 4] = "light_matrix",
         pos: [f32; 4] = "light_pos",
+        dir: [f32; 4] = "light_dir",
         color: [f32; 4] = "light_color",
         ambient: [f32; 4] = "ambient",
+        cone: [f32; 4] = "light_cone",
+        shadow: [f32; 4] = "light_shadow",
     }
 
     constant LiveBlock {
@@ -67,39 +97,243 @@ gfx_defines!{
         gamma: f32 = "gamma",
         exposure: f32 = "exposure",
         time: f32 = "time",
+        tonemap: i32 = "tonemap",
+    }
+
+    constant ExposureBlock {
+        min_ev: f32 = "min_ev",
+        max_ev: f32 = "max_ev",
+        compensation: f32 = "ev_compensation",
+        speed: f32 = "adapt_speed",
+        delta: f32 = "delta_time",
+        enabled: i32 = "auto_enabled",
+    }
+
+    constant LogLumBlock {
+        level: i32 = "log_lum_level",
+    }
+
+    constant SsaoBlock {
+        blur_dir: [f32; 4] = "blur_dir",
+        radius: f32 = "ssao_radius",
+        bias: f32 = "ssao_bias",
+        intensity: f32 = "ssao_intensity",
+        enabled: i32 = "ssao_enabled",
+    }
+
+    constant SsrBlock {
+        max_dist: f32 = "ssr_max_dist",
+        thickness: f32 = "ssr_thickness",
+        intensity: f32 = "ssr_intensity",
+        enabled: i32 = "ssr_enabled",
+        level: i32 = "hiz_level",
+        levels: i32 = "hiz_levels",
+    }
+
+    constant BloomBlock {
+        threshold: f32 = "bloom_threshold",
+        knee: f32 = "bloom_knee",
+        radius: f32 = "bloom_radius",
+        intensity: f32 = "bloom_intensity",
+        prefilter: i32 = "bloom_prefilter",
+        debug: i32 = "bloom_debug",
+    }
+
+    constant AaBlock {
+        prev_view_proj: [[f32; 4]; 4] = "prev_view_proj",
+        curr_view_proj: [[f32; 4]; 4] = "curr_view_proj",
+        jitter: [f32; 4] = "jitter",
+        feedback: f32 = "taa_feedback",
+        fxaa: i32 = "fxaa_enabled",
+    }
+
+    constant DofBlock {
+        focus_point: [f32; 4] = "focus_point",
+        focal_length: f32 = "focal_length",
+        f_stop: f32 = "f_stop",
+        sensor_height: f32 = "sensor_height",
+        enabled: i32 = "dof_enabled",
+    }
+
+    constant GradeBlock {
+        white_balance: [f32; 4] = "white_balance",
+        lift: [f32; 4] = "grade_lift",
+        gamma: [f32; 4] = "grade_gamma",
+        gain: [f32; 4] = "grade_gain",
+        domain_min: [f32; 4] = "lut_domain_min",
+        domain_max: [f32; 4] = "lut_domain_max",
+        saturation: f32 = "saturation",
+        lut_size: f32 = "lut_size",
+        lut_enabled: i32 = "lut_enabled",
+    }
+
+    constant DebugBlock {
+        shadow_clip: [f32; 4] = "shadow_clip",
+        view: i32 = "debug_view",
     }
 
     pipeline deferred {
         verts: gfx::VertexBuffer<Vtnt> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
         transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        aa: gfx::ConstantBuffer<AaBlock> = "aa",
         normal: gfx::TextureSampler<PbrTex> = "normal_tex",
-        layer_a: gfx::RenderTarget<LayerFormat> = "layer_a",
-        layer_b: gfx::RenderTarget<LayerFormat> = "layer_b",
-        depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        albedo: gfx::TextureSampler<PbrTex> = "albedo_tex",
+        metalness: gfx::TextureSampler<PbrTex> = "metalness_tex",
+        roughness: gfx::TextureSampler<PbrTex> = "roughness_tex",
+        ao: gfx::TextureSampler<PbrTex> = "ao_tex",
+        layer_n: gfx::RenderTarget<NormalFormat> = "layer_n",
+        layer_albedo: gfx::RenderTarget<AlbedoFormat> = "layer_albedo",
+        layer_material: gfx::RenderTarget<MaterialFormat> = "layer_material",
+        layer_v: gfx::RenderTarget<LayerFormat> = "layer_v",
+        depth: gfx::DepthTarget<SceneDepthFormat> = GREATER_EQUAL_WRITE,
     }
 
     pipeline pbr {
         verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
         live: gfx::ConstantBuffer<LiveBlock> = "live",
         light: gfx::ConstantBuffer<LightBlock> = "light",
-        layer_a: gfx::TextureSampler<LayerFormat> = "layer_a",
-        layer_b: gfx::TextureSampler<LayerFormat> = "layer_b",
-        albedo: gfx::TextureSampler<PbrTex> = "albedo_tex",
-        metalness: gfx::TextureSampler<PbrTex> = "metalness_tex",
-        roughness: gfx::TextureSampler<PbrTex> = "roughness_tex",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        layer_n: gfx::TextureSampler<[f32; 2]> = "layer_n",
+        layer_albedo: gfx::TextureSampler<[f32; 4]> = "layer_albedo",
+        layer_material: gfx::TextureSampler<[f32; 4]> = "layer_material",
+        ssao: gfx::TextureSampler<LayerFormat> = "ssao_tex",
         shadow: gfx::TextureSampler<f32> = "shadow_depth",
         luminance: gfx::BlendTarget<LayerFormat> = ("f_lum", gfx::state::ColorMask::all(), gfx::preset::blend::ADD),
     }
 
+    pipeline ssao {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        ssao: gfx::ConstantBuffer<SsaoBlock> = "ssao",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        layer_n: gfx::TextureSampler<[f32; 2]> = "layer_n",
+        ao: gfx::RenderTarget<LayerFormat> = "f_ao",
+    }
+
+    pipeline ssao_blur {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        ssao: gfx::ConstantBuffer<SsaoBlock> = "ssao",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        source: gfx::TextureSampler<LayerFormat> = "ao_source",
+        ao: gfx::RenderTarget<LayerFormat> = "f_ao",
+    }
+
+    pipeline hiz {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        ssr: gfx::ConstantBuffer<SsrBlock> = "ssr",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        below: gfx::TextureSampler<LayerFormat> = "hiz", // the previous level
+        target: gfx::RenderTarget<LayerFormat> = "f_depth", // one level of the chain
+        pyramid: gfx::RenderTarget<LayerFormat> = "f_pyramid", // the same level of the pyramid
+    }
+
+    pipeline ssr {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        live: gfx::ConstantBuffer<LiveBlock> = "live",
+        ssr: gfx::ConstantBuffer<SsrBlock> = "ssr",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        layer_n: gfx::TextureSampler<[f32; 2]> = "layer_n",
+        layer_albedo: gfx::TextureSampler<[f32; 4]> = "layer_albedo",
+        layer_material: gfx::TextureSampler<[f32; 4]> = "layer_material",
+        velocity: gfx::TextureSampler<LayerFormat> = "velocity",
+        hiz: gfx::TextureSampler<LayerFormat> = "hiz",
+        previous: gfx::TextureSampler<LayerFormat> = "previous",
+        luminance: gfx::BlendTarget<LayerFormat> = ("f_lum", gfx::state::ColorMask::all(), gfx::preset::blend::ADD),
+    }
+
+    pipeline dof {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        dof: gfx::ConstantBuffer<DofBlock> = "dof",
+        luminance: gfx::TextureSampler<LayerFormat> = "luminance",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        out: gfx::RenderTarget<LayerFormat> = "f_lum",
+    }
+
+    pipeline log_lum {
+        verts: gfx::VertexBuffer<V> = (),
+        luminance: gfx::TextureSampler<LayerFormat> = "luminance",
+        reduce: gfx::ConstantBuffer<LogLumBlock> = "log_lum_reduce",
+        below: gfx::TextureSampler<LayerFormat> = "log_lum", // the previous level
+        log_lum: gfx::RenderTarget<LayerFormat> = "f_log_lum", // one level of the chain
+    }
+
+    pipeline adapt {
+        verts: gfx::VertexBuffer<V> = (),
+        exposure: gfx::ConstantBuffer<ExposureBlock> = "auto_exposure",
+        log_lum: gfx::TextureSampler<LayerFormat> = "log_lum",
+        previous: gfx::TextureSampler<LayerFormat> = "previous",
+        adapted: gfx::RenderTarget<LayerFormat> = "f_adapted",
+    }
+
+    pipeline bloom_down {
+        verts: gfx::VertexBuffer<V> = (),
+        bloom: gfx::ConstantBuffer<BloomBlock> = "bloom",
+        source: gfx::TextureSampler<LayerFormat> = "source",
+        out: gfx::RenderTarget<LayerFormat> = "f_bloom",
+    }
+
+    pipeline bloom_up {
+        verts: gfx::VertexBuffer<V> = (),
+        bloom: gfx::ConstantBuffer<BloomBlock> = "bloom",
+        source: gfx::TextureSampler<LayerFormat> = "source",
+        out: gfx::BlendTarget<LayerFormat> = ("f_bloom", gfx::state::ColorMask::all(), gfx::preset::blend::ADD),
+    }
+
     pipeline ldr {
         verts: gfx::VertexBuffer<V> = (),
         live: gfx::ConstantBuffer<LiveBlock> = "live",
+        exposure: gfx::ConstantBuffer<ExposureBlock> = "auto_exposure",
         luminance: gfx::TextureSampler<LayerFormat> = "luminance",
+        adapted: gfx::TextureSampler<LayerFormat> = "adapted",
+        bloom: gfx::ConstantBuffer<BloomBlock> = "bloom",
+        bloom_tex: gfx::TextureSampler<LayerFormat> = "bloom_tex",
+        grade: gfx::ConstantBuffer<GradeBlock> = "grade",
+        lut: gfx::TextureSampler<[f32; 4]> = "lut",
+        color: gfx::RenderTarget<ColorFormat> = "f_color",
+    }
+
+    pipeline ldr_debug {
+        verts: gfx::VertexBuffer<V> = (),
+        transform: gfx::ConstantBuffer<TransformBlock> = "transform",
+        live: gfx::ConstantBuffer<LiveBlock> = "live",
+        exposure: gfx::ConstantBuffer<ExposureBlock> = "auto_exposure",
+        debug: gfx::ConstantBuffer<DebugBlock> = "debug",
+        luminance: gfx::TextureSampler<LayerFormat> = "luminance",
+        adapted: gfx::TextureSampler<LayerFormat> = "adapted",
+        depth: gfx::TextureSampler<f32> = "depth_tex",
+        layer_n: gfx::TextureSampler<[f32; 2]> = "layer_n",
+        layer_albedo: gfx::TextureSampler<[f32; 4]> = "layer_albedo",
+        layer_material: gfx::TextureSampler<[f32; 4]> = "layer_material",
+        velocity: gfx::TextureSampler<LayerFormat> = "velocity",
+        shadow: gfx::TextureSampler<f32> = "shadow_depth",
+        color: gfx::RenderTarget<ColorFormat> = "f_color",
+    }
+
+    pipeline taa {
+        verts: gfx::VertexBuffer<V> = (),
+        aa: gfx::ConstantBuffer<AaBlock> = "aa",
+        current: gfx::TextureSampler<[f32; 4]> = "current",
+        history: gfx::TextureSampler<[f32; 4]> = "history",
+        velocity: gfx::TextureSampler<LayerFormat> = "velocity",
+        out: gfx::RenderTarget<LayerFormat> = "f_color",
+    }
+
+    pipeline fxaa {
+        verts: gfx::VertexBuffer<V> = (),
+        aa: gfx::ConstantBuffer<AaBlock> = "aa",
+        source: gfx::TextureSampler<[f32; 4]> = "source",
         color: gfx::RenderTarget<ColorFormat> = "f_color",
     }
 
     pipeline shadow {
         verts: gfx::VertexBuffer<Vtnt> = (),
+        instances: gfx::InstanceBuffer<Instance> = (),
         transform: gfx::ConstantBuffer<TransformBlock> = "transform",
         depth: gfx::DepthTarget<ShadowDepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
     }
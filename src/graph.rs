@@ -0,0 +1,268 @@
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3, Matrix4, Quaternion, Deg, vec3};
+
+use camera::Plane;
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(p: Point3<f32>) -> Aabb {
+        Aabb {
+            min: p,
+            max: p,
+        }
+    }
+
+    pub fn extend(&mut self, p: Point3<f32>) {
+        self.min = Point3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = Point3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut out = *self;
+        out.extend(other.min);
+        out.extend(other.max);
+        out
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// The sphere through the corners, not the tightest one but close enough for framing.
+    pub fn sphere(&self) -> Sphere {
+        Sphere {
+            center: self.center(),
+            radius: (self.max - self.min).magnitude() * 0.5,
+        }
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point3::new(a.x, a.y, a.z), Point3::new(b.x, a.y, a.z),
+            Point3::new(a.x, b.y, a.z), Point3::new(b.x, b.y, a.z),
+            Point3::new(a.x, a.y, b.z), Point3::new(b.x, a.y, b.z),
+            Point3::new(a.x, b.y, b.z), Point3::new(b.x, b.y, b.z),
+        ]
+    }
+
+    /// False only when the box is entirely behind one of the planes, so a few boxes just outside
+    /// the corners of a frustum still count as inside.
+    pub fn in_frustum(&self, planes: &[Plane]) -> bool {
+        planes.iter().all(|p| {
+            // the corner furthest along the normal
+            let corner = Point3::new(
+                if p.normal.x >= 0. { self.max.x } else { self.min.x },
+                if p.normal.y >= 0. { self.max.y } else { self.min.y },
+                if p.normal.z >= 0. { self.max.z } else { self.min.z });
+            p.distance(corner) >= 0.
+        })
+    }
+
+    /// The box around this one after it is transformed (which is usually a bit larger).
+    pub fn transform(&self, m: &Matrix4<f32>) -> Aabb {
+        let corners = self.corners();
+        let mut out = Aabb::new(m.transform_point(corners[0]));
+        for &c in &corners[1..] {
+            out.extend(m.transform_point(c));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+/// Translation, rotation, scale; applied in reverse (scale first).
+#[derive(Clone, Copy, Debug)]
+pub struct Trs {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Trs {
+    pub fn identity() -> Trs {
+        Trs {
+            translation: vec3(0., 0., 0.),
+            rotation: Quaternion::one(),
+            scale: vec3(1., 1., 1.),
+        }
+    }
+
+    /// Rotation is in degrees around x, then y, then z.
+    pub fn from_euler(translation: Vector3<f32>, rotation: Vector3<f32>, scale: f32) -> Trs {
+        Trs {
+            translation: translation,
+            rotation: Quaternion::from_angle_z(Deg(rotation.z))
+                * Quaternion::from_angle_y(Deg(rotation.y))
+                * Quaternion::from_angle_x(Deg(rotation.x)),
+            scale: vec3(scale, scale, scale),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// What normals go through when positions go through `m`: the inverse transpose, so they stay
+/// perpendicular to the surface under non-uniform scale. Only the upper 3x3 is meaningful.
+pub fn normal_matrix(m: &Matrix4<f32>) -> Matrix4<f32> {
+    m.invert().unwrap_or(Matrix4::identity()).transpose()
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub local: Trs,
+    pub mesh_bounds: Option<Aabb>, // in local space, if the node draws anything
+
+    // filled in by Graph::update
+    pub world: Matrix4<f32>,
+    pub bounds: Option<Aabb>, // world space, including all children
+}
+
+/// Parts drawn and skipped by frustum culling in one frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+/// A flat list of nodes where parents always come before their children.
+#[derive(Clone, Debug)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph {
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: String, parent: Option<usize>, local: Trs, mesh_bounds: Option<Aabb>) -> usize {
+        assert!(parent.map(|p| p < self.nodes.len()).unwrap_or(true), "Parent nodes must be added first");
+
+        self.nodes.push(Node {
+            name: name,
+            parent: parent,
+            local: local,
+            mesh_bounds: mesh_bounds,
+            world: Matrix4::identity(),
+            bounds: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Recomputes world matrices top-down, then world bounds bottom-up.
+    pub fn update(&mut self) {
+        for i in 0..self.nodes.len() {
+            let parent = match self.nodes[i].parent {
+                Some(p) => self.nodes[p].world,
+                None => Matrix4::identity(),
+            };
+            let node = &mut self.nodes[i];
+            let world = parent * node.local.matrix();
+            node.world = world;
+            node.bounds = node.mesh_bounds.map(|b| b.transform(&world));
+        }
+
+        for i in (0..self.nodes.len()).rev() {
+            if let (Some(p), Some(b)) = (self.nodes[i].parent, self.nodes[i].bounds) {
+                let parent = &mut self.nodes[p];
+                parent.bounds = Some(match parent.bounds {
+                    Some(pb) => pb.union(&b),
+                    None => b,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::prelude::*;
+    use cgmath::{Point3, Vector3, Matrix4, vec3};
+
+    use super::*;
+
+    fn near(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    fn unit_box() -> Aabb {
+        let mut b = Aabb::new(Point3::new(-1., -1., -1.));
+        b.extend(Point3::new(1., 1., 1.));
+        b
+    }
+
+    fn origin(graph: &Graph, node: usize) -> Vector3<f32> {
+        graph.nodes[node].world.transform_point(Point3::new(0., 0., 0.)).to_vec()
+    }
+
+    #[test]
+    fn world_matrices() {
+        let mut graph = Graph::new();
+        let root = graph.add("car".to_owned(), None, Trs::from_euler(vec3(10., 0., 0.), vec3(0., 90., 0.), 2.), None);
+        let wheel = graph.add("wheel".to_owned(), Some(root), Trs::from_euler(vec3(1., 0., 0.), vec3(0., 0., 0.), 0.5), Some(unit_box()));
+        let hub = graph.add("hub".to_owned(), Some(wheel), Trs::identity(), None);
+        graph.update();
+
+        // the child's offset is turned and scaled by its parent before the parent's translation
+        assert!(near(origin(&graph, root), vec3(10., 0., 0.)));
+        assert!(near(origin(&graph, wheel), vec3(10., 0., -2.)));
+        assert!(near(origin(&graph, hub), origin(&graph, wheel)));
+
+        let world = graph.nodes[root].world * graph.nodes[wheel].local.matrix();
+        for i in 0..4 {
+            assert!(near((graph.nodes[wheel].world[i] - world[i]).truncate(), Vector3::zero()));
+        }
+        let scale = graph.nodes[wheel].world.transform_vector(vec3(1., 0., 0.)).magnitude();
+        assert!((scale - 1.).abs() < 1e-4);
+
+        // moving the parent moves the child on the next update
+        graph.nodes[root].local.translation = vec3(0., 5., 0.);
+        graph.update();
+        assert!(near(origin(&graph, wheel), vec3(0., 5., -2.)));
+        assert!(near(origin(&graph, hub), vec3(0., 5., -2.)));
+    }
+
+    #[test]
+    fn bounds_reach_the_root() {
+        let mut graph = Graph::new();
+        let root = graph.add("root".to_owned(), None, Trs::identity(), None);
+        let a = graph.add("a".to_owned(), Some(root), Trs::from_euler(vec3(-3., 0., 0.), vec3(0., 0., 0.), 1.), Some(unit_box()));
+        graph.add("b".to_owned(), Some(root), Trs::from_euler(vec3(3., 0., 0.), vec3(0., 0., 0.), 1.), Some(unit_box()));
+        graph.update();
+
+        let b = graph.nodes[a].bounds.unwrap();
+        assert!(near(b.min.to_vec(), vec3(-4., -1., -1.)) && near(b.max.to_vec(), vec3(-2., 1., 1.)));
+        let b = graph.nodes[root].bounds.unwrap();
+        assert!(near(b.min.to_vec(), vec3(-4., -1., -1.)) && near(b.max.to_vec(), vec3(4., 1., 1.)));
+    }
+
+    #[test]
+    fn normals_under_scale() {
+        let m = Matrix4::from_nonuniform_scale(4., 1., 1.);
+        // a slanted surface stays perpendicular to its stretched tangent
+        let (t, n) = (vec3(1., -1., 0.), vec3(1., 1., 0.));
+        let t = m.transform_vector(t);
+        let n = normal_matrix(&m).transform_vector(n);
+        assert!(t.dot(n).abs() < 1e-4);
+    }
+}
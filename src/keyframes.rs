@@ -0,0 +1,290 @@
+use std::io::{self, BufReader, BufRead, Write};
+use std::path::Path;
+use std::fs::File;
+
+use cgmath::{Point3, Deg};
+
+/// Seconds between keys recorded from the app; edit the file for other timings.
+pub const KEY_SPACING: f32 = 2.;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CameraKey {
+    pub time: f32,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub fov: Deg<f32>, // vertical
+    pub focus: f32, // focus distance
+}
+
+impl CameraKey {
+    fn to_array(&self) -> [f32; 8] {
+        [self.eye.x, self.eye.y, self.eye.z, self.target.x, self.target.y, self.target.z, self.fov.0, self.focus]
+    }
+
+    fn from_array(time: f32, a: [f32; 8]) -> CameraKey {
+        CameraKey {
+            time: time,
+            eye: Point3::new(a[0], a[1], a[2]),
+            target: Point3::new(a[3], a[4], a[5]),
+            fov: Deg(a[6]),
+            focus: a[7],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    CatmullRom, // passes through every key
+    Bezier, // the keys are the control points of one curve, smoother but only touches the ends
+}
+
+impl Interpolation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::CatmullRom => "catmull_rom",
+            Interpolation::Bezier => "bezier",
+        }
+    }
+}
+
+/// Keyframes sorted by time.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub keys: Vec<CameraKey>,
+    pub interpolation: Interpolation,
+}
+
+fn lerp(a: [f32; 8], b: [f32; 8], t: f32) -> [f32; 8] {
+    let mut out = a;
+    for (o, &b) in out.iter_mut().zip(&b) {
+        *o += (b - *o) * t;
+    }
+    out
+}
+
+fn catmull_rom(p0: [f32; 8], p1: [f32; 8], p2: [f32; 8], p3: [f32; 8], t: f32) -> [f32; 8] {
+    let (t2, t3) = (t * t, t * t * t);
+    let mut out = [0.; 8];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = 0.5 * (2. * p1[i]
+            + (p2[i] - p0[i]) * t
+            + (2. * p0[i] - 5. * p1[i] + 4. * p2[i] - p3[i]) * t2
+            + (3. * p1[i] - p0[i] - 3. * p2[i] + p3[i]) * t3);
+    }
+    out
+}
+
+impl CameraPath {
+    pub fn new() -> CameraPath {
+        CameraPath {
+            keys: Vec::new(),
+            interpolation: Interpolation::CatmullRom,
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map(|k| k.time).unwrap_or(0.)
+    }
+
+    /// The camera at `time`, held at the first and last keys outside of the path.
+    pub fn sample(&self, time: f32) -> Option<CameraKey> {
+        let (first, last) = match (self.keys.first(), self.keys.last()) {
+            (Some(f), Some(l)) => (f, l),
+            _ => return None,
+        };
+        if time <= first.time { return Some(*first) }
+        if time >= last.time { return Some(*last) }
+
+        let points: Vec<_> = self.keys.iter().map(|k| k.to_array()).collect();
+
+        let value = match self.interpolation {
+            Interpolation::CatmullRom => {
+                let i = self.keys.iter().position(|k| k.time > time).unwrap() - 1;
+                let (k1, k2) = (&self.keys[i], &self.keys[i + 1]);
+                let t = (time - k1.time) / (k2.time - k1.time);
+
+                // the ends are repeated so the curve still reaches them
+                let p0 = points[if i > 0 { i - 1 } else { 0 }];
+                let p3 = points[(i + 2).min(points.len() - 1)];
+                catmull_rom(p0, points[i], points[i + 1], p3, t)
+            },
+            Interpolation::Bezier => {
+                // de Casteljau over all the keys
+                let mut points = points;
+                let t = (time - first.time) / (last.time - first.time);
+                while points.len() > 1 {
+                    points = points.windows(2).map(|w| lerp(w[0], w[1], t)).collect();
+                }
+                points[0]
+            },
+        };
+
+        Some(CameraKey::from_array(time, value))
+    }
+}
+
+//
+// Path files are one directive per line:
+//   interpolation catmull_rom | bezier
+//   key [time] [eye x y z] [target x y z] [fov deg] [focus]
+//
+
+fn next_f32<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<f32, usize> {
+    line.next().ok_or(linen)?.parse().or(Err(linen))
+}
+
+fn next_point<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<Point3<f32>, usize> {
+    Ok(Point3::new(next_f32(line, linen)?, next_f32(line, linen)?, next_f32(line, linen)?))
+}
+
+pub fn load_path<R: BufRead>(read: R) -> Result<CameraPath, usize> {
+    let mut path = CameraPath::new();
+
+    let mut linen = 0usize;
+
+    for line in read.lines() {
+        linen += 1;
+
+        let line = line.or(Err(linen))?;
+        let mut line = line.split('#').next().unwrap_or("").split_whitespace();
+
+        match line.next() {
+            Some("interpolation") => {
+                path.interpolation = match line.next() {
+                    Some("catmull_rom") => Interpolation::CatmullRom,
+                    Some("bezier") => Interpolation::Bezier,
+                    _ => return Err(linen),
+                }
+            },
+            Some("key") => {
+                let key = CameraKey {
+                    time: next_f32(&mut line, linen)?,
+                    eye: next_point(&mut line, linen)?,
+                    target: next_point(&mut line, linen)?,
+                    fov: Deg(next_f32(&mut line, linen)?),
+                    focus: next_f32(&mut line, linen)?,
+                };
+                if path.keys.last().map(|k| k.time >= key.time).unwrap_or(false) { return Err(linen) }
+                path.keys.push(key);
+            },
+            Some(_) => return Err(linen),
+            None => (),
+        }
+    }
+
+    Ok(path)
+}
+
+pub fn open_path<P: AsRef<Path>>(path: P) -> Result<CameraPath, String> {
+    let display = format!("{}", path.as_ref().display());
+
+    match File::open(path) {
+        Ok(f) => match load_path(BufReader::new(f)) {
+            Ok(path) => Ok(path),
+            Err(line) => Err(format!("Error parsing \"{}\" on line #{}", display, line)),
+        },
+        Err(e) => Err(format!("File \"{}\" could not be opened: {:?}", display, e)),
+    }
+}
+
+pub fn write_path<W: Write>(path: &CameraPath, w: &mut W) -> io::Result<()> {
+    writeln!(w, "interpolation {}", path.interpolation.name())?;
+    for k in &path.keys {
+        writeln!(w, "key {} {} {} {} {} {} {} {} {}",
+            k.time, k.eye.x, k.eye.y, k.eye.z, k.target.x, k.target.y, k.target.z, k.fov.0, k.focus)?;
+    }
+    Ok(())
+}
+
+pub fn save_path<P: AsRef<Path>>(path: &CameraPath, file: P) -> Result<(), String> {
+    let display = format!("{}", file.as_ref().display());
+
+    match File::create(file) {
+        Ok(mut f) => write_path(path, &mut f)
+            .map_err(|e| format!("File \"{}\" could not be written: {:?}", display, e)),
+        Err(e) => Err(format!("File \"{}\" could not be created: {:?}", display, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+
+    // the eye moves along x at one unit per second, the focus goes up and back down
+    const PATH: &'static str = "\
+interpolation catmull_rom
+key 0 0 1 5 0 0 0 45 5
+key 2 2 1 5 0 0 0 45 7 # a comment
+key 4 4 1 5 0 0 0 45 5
+key 6 6 1 5 0 0 0 45 5
+";
+
+    fn path(interpolation: Interpolation) -> CameraPath {
+        CameraPath { interpolation: interpolation, .. load_path(PATH.as_bytes()).unwrap() }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn load() {
+        let path = load_path(PATH.as_bytes()).unwrap();
+        assert_eq!(path.interpolation, Interpolation::CatmullRom);
+        assert_eq!(path.keys.len(), 4);
+        assert_eq!(path.duration(), 6.);
+
+        let key = path.keys[1];
+        assert_eq!((key.time, key.eye, key.target), (2., Point3::new(2., 1., 5.), Point3::new(0., 0., 0.)));
+        assert_eq!((key.fov, key.focus), (Deg(45.), 7.));
+
+        let bezier = load_path("interpolation bezier\n".as_bytes()).unwrap();
+        assert_eq!(bezier.interpolation, Interpolation::Bezier);
+        assert!(bezier.sample(1.).is_none());
+    }
+
+    #[test]
+    fn reject() {
+        let line = |text: &str| load_path(text.as_bytes()).unwrap_err();
+
+        assert_eq!(line("interpolation linear"), 1);
+        assert_eq!(line("key 0 0 0 0 0 0 0 45"), 1); // no focus
+        assert_eq!(line("key 0 0 0 0 0 0 0 45 five"), 1);
+        assert_eq!(line("key 1 0 0 0 0 0 0 45 5\nkey 1 0 0 0 0 0 0 45 5"), 2); // time has to move forward
+        assert_eq!(line("\nfov 45"), 2);
+    }
+
+    #[test]
+    fn catmull_rom_keys() {
+        let path = path(Interpolation::CatmullRom);
+
+        // every key is passed through, and the ends are held
+        for key in &path.keys {
+            let k = path.sample(key.time).unwrap();
+            assert!(close(k.eye.x, key.eye.x) && close(k.focus, key.focus), "{:?} isn't {:?}", k, key);
+        }
+        assert!(close(path.sample(-1.).unwrap().eye.x, 0.));
+        assert!(close(path.sample(10.).unwrap().eye.x, 6.));
+
+        // evenly spaced keys on a line stay on it, the focus curves over the straight line
+        let k = path.sample(3.).unwrap();
+        assert!(close(k.eye.x, 3.) && close(k.eye.y, 1.) && close(k.fov.0, 45.));
+        assert!(close(k.focus, 6.125));
+    }
+
+    #[test]
+    fn bezier_keys() {
+        let mut path = path(Interpolation::Bezier);
+        path.keys.truncate(3);
+
+        // only the ends are touched
+        assert!(close(path.sample(0.).unwrap().eye.x, 0.));
+        assert!(close(path.sample(4.).unwrap().eye.x, 4.));
+        assert!(close(path.sample(2.).unwrap().focus, 6.));
+
+        let k = path.sample(1.).unwrap();
+        assert!(close(k.eye.x, 1.) && close(k.focus, 5. + 2. * 2. * 0.25 * 0.75));
+    }
+}
@@ -0,0 +1,191 @@
+use std::io::{BufReader, BufRead};
+use std::path::Path;
+use std::fs::File;
+
+use gfx;
+use gfx::texture;
+use gfx::format::{R16_G16_B16_A16, Unorm};
+use gfx::handle::ShaderResourceView;
+
+/// A 3D color lookup table, red varies fastest, then green, then blue.
+#[derive(Clone, Debug)]
+pub struct Lut {
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    /// The smallest table that leaves colors unchanged (trilinear filtering does the rest).
+    pub fn identity() -> Lut {
+        let mut data = Vec::new();
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    data.push([r as f32, g as f32, b as f32]);
+                }
+            }
+        }
+
+        Lut {
+            size: 2,
+            domain_min: [0.; 3],
+            domain_max: [1.; 3],
+            data: data,
+        }
+    }
+
+    pub fn create_texture<R, F>(&self, factory: &mut F) -> ShaderResourceView<R, [f32; 4]>
+        where R: gfx::Resources, F: gfx::Factory<R>
+    {
+        let data: Vec<[u16; 4]> = self.data.iter()
+            .map(|c| [to_u16(c[0]), to_u16(c[1]), to_u16(c[2]), 0xFFFF])
+            .collect();
+        let size = self.size as texture::Size;
+
+        factory.create_texture_immutable::<(R16_G16_B16_A16, Unorm)>(
+            texture::Kind::D3(size, size, size),
+            &[&data]
+        ).expect("Could not upload LUT").1
+    }
+}
+
+fn to_u16(v: f32) -> u16 {
+    (v.max(0.).min(1.) * 65535. + 0.5) as u16
+}
+
+/// Why a .cube file couldn't be loaded, with the line it happened on.
+#[derive(Debug, PartialEq)]
+pub enum CubeError {
+    Parse(usize),
+    OneDimensional(usize),
+}
+
+impl From<usize> for CubeError {
+    fn from(line: usize) -> CubeError {
+        CubeError::Parse(line)
+    }
+}
+
+fn next_rgb<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<[f32; 3], usize> {
+    let mut rgb = [0.; 3];
+    for c in &mut rgb {
+        *c = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+    }
+    Ok(rgb)
+}
+
+/// Parses an Adobe/Resolve .cube file. Only 3D tables are supported.
+pub fn load_cube<R: BufRead>(read: R) -> Result<Lut, CubeError> {
+    let mut lut = Lut {
+        size: 0,
+        domain_min: [0.; 3],
+        domain_max: [1.; 3],
+        data: Vec::new(),
+    };
+
+    let mut linen = 0usize;
+
+    for line in read.lines() {
+        linen += 1;
+
+        let line = line.or(Err(linen))?;
+        let mut line = line.split('#').next().unwrap_or("").split_whitespace();
+
+        match line.next() {
+            Some("TITLE") => (),
+            Some("LUT_3D_SIZE") => {
+                lut.size = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+                if lut.size < 2 || lut.size > 256 { return Err(CubeError::Parse(linen)) }
+            },
+            Some("DOMAIN_MIN") => lut.domain_min = next_rgb(&mut line, linen)?,
+            Some("DOMAIN_MAX") => lut.domain_max = next_rgb(&mut line, linen)?,
+            Some("LUT_3D_INPUT_RANGE") => {
+                let min: f32 = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+                let max: f32 = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+                lut.domain_min = [min; 3];
+                lut.domain_max = [max; 3];
+            },
+            Some("LUT_1D_SIZE") | Some("LUT_1D_INPUT_RANGE") => return Err(CubeError::OneDimensional(linen)),
+            Some(first) => {
+                let mut rest = Some(first).into_iter().chain(line);
+                if lut.size == 0 { return Err(CubeError::Parse(linen)) } // data before the size
+                lut.data.push(next_rgb(&mut rest, linen)?);
+            },
+            None => (),
+        }
+    }
+
+    if lut.size == 0 || lut.data.len() != lut.size * lut.size * lut.size {
+        return Err(CubeError::Parse(linen));
+    }
+
+    Ok(lut)
+}
+
+pub fn open_cube<P: AsRef<Path>>(path: P) -> Result<Lut, String> {
+    let display = format!("{}", path.as_ref().display());
+
+    match File::open(path) {
+        Ok(f) => match load_cube(BufReader::new(f)) {
+            Ok(lut) => Ok(lut),
+            Err(CubeError::Parse(line)) => Err(format!("Error parsing \"{}\" on line #{}", display, line)),
+            Err(CubeError::OneDimensional(line)) =>
+                Err(format!("\"{}\" is a 1D LUT (line #{}), 1D LUTs are not supported", display, line)),
+        },
+        Err(e) => Err(format!("File \"{}\" could not be opened: {:?}", display, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &'static str = "\
+TITLE \"identity\"
+# red varies fastest
+LUT_3D_SIZE 2
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    #[test]
+    fn identity() {
+        let lut = load_cube(IDENTITY.as_bytes()).unwrap();
+        let expected = Lut::identity();
+        assert_eq!(lut.size, expected.size);
+        assert_eq!(lut.data, expected.data);
+        assert_eq!((lut.domain_min, lut.domain_max), ([0.; 3], [1.; 3]));
+    }
+
+    #[test]
+    fn domains() {
+        let text = format!("DOMAIN_MIN 0 -0.5 0\nDOMAIN_MAX 1 2 4\n{}", IDENTITY);
+        let lut = load_cube(text.as_bytes()).unwrap();
+        assert_eq!((lut.domain_min, lut.domain_max), ([0., -0.5, 0.], [1., 2., 4.]));
+
+        let text = format!("LUT_3D_INPUT_RANGE -0.25 1.5\n{}", IDENTITY);
+        let lut = load_cube(text.as_bytes()).unwrap();
+        assert_eq!((lut.domain_min, lut.domain_max), ([-0.25; 3], [1.5; 3]));
+    }
+
+    #[test]
+    fn rejects() {
+        // data before the size, a missing row, a short row, a size out of range, a bad range
+        assert_eq!(load_cube("0 0 0\nLUT_3D_SIZE 2\n".as_bytes()).unwrap_err(), CubeError::Parse(1));
+        assert_eq!(load_cube(IDENTITY.replace("1 1 1\n", "").as_bytes()).unwrap_err(), CubeError::Parse(10));
+        assert_eq!(load_cube(IDENTITY.replace("1 1 0", "1 1").as_bytes()).unwrap_err(), CubeError::Parse(7));
+        assert_eq!(load_cube(IDENTITY.replace("SIZE 2", "SIZE 1").as_bytes()).unwrap_err(), CubeError::Parse(3));
+        assert_eq!(load_cube(format!("LUT_3D_INPUT_RANGE 0\n{}", IDENTITY).as_bytes()).unwrap_err(),
+            CubeError::Parse(1));
+
+        assert_eq!(load_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n".as_bytes()).unwrap_err(), CubeError::OneDimensional(1));
+    }
+}
@@ -1,7 +1,12 @@
 #[macro_use]
 extern crate gfx; // Safe (Rust-friendly) graphics
 extern crate gfx_app; // easy main loop/other window stuff
+extern crate gfx_window_glutin; // creates the OpenGL device and factory for a glutin window
+extern crate gfx_device_gl; // the OpenGL device
+extern crate gfx_gl; // raw OpenGL, for the state gfx doesn't manage
+extern crate glutin; // OpenGL contexts
 extern crate winit; // windowing library, built on/part of glutin (equiv. to GLFW)
+extern crate env_logger; // log output, RUST_LOG picks the level
 
 extern crate cgmath; // Math library, like glm
 
@@ -15,12 +20,98 @@ mod camera; // camera.rs
 mod define;
 mod app;
 mod wavefront;
+mod rig;
+mod post;
+mod lut;
+mod scene;
+mod graph;
+mod keyframes;
+mod pick;
+mod shapes;
 
 pub const DEFAULT_SIZE: (u32, u32) = (1024, 1024);
+const TITLE: &'static str = "PBR with gfx-rs";
 
 fn main() {
     let wb = winit::WindowBuilder::new()
-        .with_title("PBR with gfx-rs")
+        .with_title(TITLE)
         .with_dimensions(DEFAULT_SIZE.0, DEFAULT_SIZE.1);
-    gfx_app::launch_gl3::<app::App<_, _>>(wb);
-}
\ No newline at end of file
+    launch::<app::App<_, _>>(wb);
+}
+
+/// `gfx_app::launch_gl3`, plus the `app::Hooks` calls. gfx_app 0.5 only hands the factory to
+/// `new` and `on_resize`, so its loop can't be reused for anything that needs it every frame.
+fn launch<A>(wb: winit::WindowBuilder) where
+    A: app::Hooks<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
+{
+    use std::time::Instant;
+    use gfx::Device;
+    use gfx_app::{WindowTargets, ColorFormat, DepthFormat};
+
+    env_logger::init().unwrap();
+    let builder = glutin::WindowBuilder::from_winit_builder(wb)
+        .with_gl(glutin::GlRequest::GlThenGles {
+            opengl_version: (3, 2),
+            opengles_version: (2, 0),
+        })
+        .with_vsync();
+    let (window, mut device, mut factory, main_color, main_depth) =
+        gfx_window_glutin::init::<ColorFormat, DepthFormat>(builder);
+    let (mut cur_width, mut cur_height) = window.get_inner_size_points().unwrap();
+
+    let shade_lang = device.get_info().shading_language;
+    let backend = if shade_lang.is_embedded {
+        gfx_app::shade::Backend::GlslEs(shade_lang)
+    } else {
+        gfx_app::shade::Backend::Glsl(shade_lang)
+    };
+    let mut app = A::new(&mut factory, backend, WindowTargets {
+        color: main_color,
+        depth: main_depth,
+        aspect_ratio: cur_width as f32 / cur_height as f32,
+    });
+
+    // reverse-Z only gains precision with clip space depth from 0 to 1, gfx leaves it at -1 to 1
+    let zero_to_one = device.get_info().is_version_or_extension_supported(4, 5, "GL_ARB_clip_control");
+    if zero_to_one {
+        unsafe { device.with_gl(|gl| gl.ClipControl(gfx_gl::LOWER_LEFT, gfx_gl::ZERO_TO_ONE)) };
+    } else {
+        println!("No glClipControl (OpenGL 4.5 or GL_ARB_clip_control), reverse-Z depth goes from -1 to 1");
+    }
+    app.set_zero_to_one(zero_to_one);
+
+    let start = Instant::now();
+    let mut frames = 0;
+    'main: loop {
+        for event in window.poll_events() {
+            match event {
+                winit::Event::Closed => break 'main,
+                winit::Event::KeyboardInput(winit::ElementState::Pressed, _, key) if key == A::get_exit_key() => break 'main,
+                winit::Event::Resized(width, height) => if width != cur_width || height != cur_height {
+                    cur_width = width;
+                    cur_height = height;
+                    let (new_color, new_depth) = gfx_window_glutin::new_views(&window);
+                    app.on_resize(&mut factory, WindowTargets {
+                        color: new_color,
+                        depth: new_depth,
+                        aspect_ratio: width as f32 / height as f32,
+                    });
+                },
+                _ => app.on(event),
+            }
+        }
+
+        app.update(&mut factory);
+        if let Some(status) = app.status() {
+            window.set_title(&format!("{} - {}", TITLE, status));
+        }
+        app.render(&mut device);
+        window.swap_buffers().unwrap();
+        device.cleanup();
+        frames += 1;
+    }
+
+    let elapsed = start.elapsed();
+    let ms = elapsed.as_secs() as f64 * 1000. + elapsed.subsec_nanos() as f64 / 1e6;
+    println!("Avg frame time: {} ms", ms / frames as f64);
+}
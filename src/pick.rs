@@ -0,0 +1,327 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use cgmath::prelude::*;
+use cgmath::{Point2, Point3, Vector3, Matrix4};
+use image::{self, Rgba, RgbaImage};
+
+use camera::Ray;
+use define::Vtnt;
+use graph::Aabb;
+
+/// Triangles per leaf of the hierarchy.
+const LEAF_SIZE: usize = 4;
+/// Largest side of the CPU copy of a material map. A readout needs no more than this, and the
+/// full maps already live on the GPU.
+const MATERIAL_SIZE: u32 = 128;
+
+/// A surface point, in whatever space the ray was in.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub t: f32, // distance along the ray, in units of the ray direction
+    pub position: Point3<f32>,
+    pub normal: Vector3<f32>, // interpolated vertex normal, before the normal map
+    pub uv: Point2<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BvhNode {
+    Leaf { bounds: Aabb, start: usize, end: usize },
+    Branch { bounds: Aabb, left: usize, right: usize },
+}
+
+/// A bounding volume hierarchy over the triangles of one mesh part, in the part's local space.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    positions: Vec<Point3<f32>>,
+    // only read for the closest hit
+    normals: Vec<Vector3<f32>>,
+    uvs: Vec<Point2<f32>>,
+    tris: Vec<[u32; 3]>, // into the vertex arrays, ordered so that every leaf is a range
+    nodes: Vec<BvhNode>,
+}
+
+fn centroid(tri: &[u32; 3], positions: &[Point3<f32>]) -> Point3<f32> {
+    Point3::centroid(&[positions[tri[0] as usize], positions[tri[1] as usize], positions[tri[2] as usize]])
+}
+
+fn build(tris: &mut [[u32; 3]], positions: &[Point3<f32>], offset: usize, nodes: &mut Vec<BvhNode>) -> usize {
+    let mut bounds = Aabb::new(positions[tris[0][0] as usize]);
+    for &i in tris.iter().flat_map(|t| t.iter()) {
+        bounds.extend(positions[i as usize]);
+    }
+
+    let index = nodes.len();
+    nodes.push(BvhNode::Leaf { bounds: bounds, start: offset, end: offset + tris.len() });
+    if tris.len() <= LEAF_SIZE { return index }
+
+    // split at the median along the longest axis
+    let size = bounds.max - bounds.min;
+    let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+    tris.sort_by(|a, b| {
+        centroid(a, positions)[axis].partial_cmp(&centroid(b, positions)[axis]).unwrap_or(Ordering::Equal)
+    });
+
+    let mid = tris.len() / 2;
+    let (l, r) = tris.split_at_mut(mid);
+    let left = build(l, positions, offset, nodes);
+    let right = build(r, positions, offset + mid, nodes);
+    nodes[index] = BvhNode::Branch { bounds: bounds, left: left, right: right };
+    index
+}
+
+/// Slab test, true if the ray enters the box before `max_t`.
+fn hits_box(b: &Aabb, ray: &Ray, max_t: f32) -> bool {
+    let axes = [
+        (ray.origin.x, ray.dir.x, b.min.x, b.max.x),
+        (ray.origin.y, ray.dir.y, b.min.y, b.max.y),
+        (ray.origin.z, ray.dir.z, b.min.z, b.max.z),
+    ];
+
+    let (mut t0, mut t1) = (0f32, max_t);
+    for &(o, d, lo, hi) in &axes {
+        let inv = 1. / d;
+        let (near, far) = ((lo - o) * inv, (hi - o) * inv);
+        t0 = t0.max(near.min(far));
+        t1 = t1.min(near.max(far));
+        if t0 > t1 { return false }
+    }
+    true
+}
+
+/// Möller-Trumbore, hitting both sides of the triangle. Gives t and the barycentric weights of
+/// the second and third corners.
+fn hit_triangle(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>, ray: &Ray) -> Option<(f32, f32, f32)> {
+    let (e1, e2) = (b - a, c - a);
+
+    let p = ray.dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-12 { return None }
+
+    let inv = 1. / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv;
+    if u < 0. || u > 1. { return None }
+
+    let q = s.cross(e1);
+    let v = ray.dir.dot(q) * inv;
+    if v < 0. || u + v > 1. { return None }
+
+    let t = e2.dot(q) * inv;
+    if t <= 0. { return None }
+
+    Some((t, u, v))
+}
+
+impl Bvh {
+    /// `indices` is a triangle list into `verts`. Only the vertices it uses are kept.
+    pub fn new(verts: &[Vtnt], indices: &[u32]) -> Bvh {
+        let mut bvh = Bvh {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            tris: Vec::new(),
+            nodes: Vec::new(),
+        };
+
+        let mut remap = HashMap::new();
+        for c in indices.chunks(3).filter(|c| c.len() == 3) {
+            let mut tri = [0; 3];
+            for (t, &i) in tri.iter_mut().zip(c) {
+                *t = *remap.entry(i).or_insert_with(|| {
+                    let v = &verts[i as usize];
+                    bvh.positions.push(Point3::from(v.a_pos));
+                    bvh.normals.push(Vector3::from(v.a_nor));
+                    bvh.uvs.push(Point2::from(v.a_tex));
+                    bvh.positions.len() as u32 - 1
+                });
+            }
+            bvh.tris.push(tri);
+        }
+
+        if !bvh.tris.is_empty() {
+            build(&mut bvh.tris[..], &bvh.positions, 0, &mut bvh.nodes);
+        }
+        bvh
+    }
+
+    /// The closest hit in local space.
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let mut best: Option<(f32, [u32; 3], f32, f32)> = None;
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+
+        while let Some(i) = stack.pop() {
+            let max_t = best.map(|h| h.0).unwrap_or(::std::f32::INFINITY);
+            match self.nodes[i] {
+                BvhNode::Leaf { bounds, start, end } => {
+                    if !hits_box(&bounds, ray, max_t) { continue }
+                    for tri in &self.tris[start..end] {
+                        let p = |c: usize| self.positions[tri[c] as usize];
+                        match hit_triangle(p(0), p(1), p(2), ray) {
+                            Some((t, u, v)) if best.map(|b| t < b.0).unwrap_or(true) => best = Some((t, *tri, u, v)),
+                            _ => (),
+                        }
+                    }
+                },
+                BvhNode::Branch { bounds, left, right } => {
+                    if !hits_box(&bounds, ray, max_t) { continue }
+                    stack.push(left);
+                    stack.push(right);
+                },
+            }
+        }
+
+        best.map(|(t, tri, u, v)| {
+            let w = 1. - u - v;
+            let normal = |c: usize| self.normals[tri[c] as usize];
+            let uv = |c: usize| self.uvs[tri[c] as usize].to_vec();
+            Hit {
+                t: t,
+                position: ray.at(t),
+                normal: (normal(0) * w + normal(1) * u + normal(2) * v).normalize(),
+                uv: Point2::from_vec(uv(0) * w + uv(1) * u + uv(2) * v),
+            }
+        })
+    }
+
+    /// The closest hit of a world space ray against the mesh placed by `world`, in world space.
+    pub fn cast(&self, world: &Matrix4<f32>, ray: &Ray) -> Option<Hit> {
+        let inv = match world.invert() {
+            Some(inv) => inv,
+            None => return None,
+        };
+
+        // the direction is not renormalized, so t is the same in both spaces
+        let local = Ray {
+            origin: inv.transform_point(ray.origin),
+            dir: inv.transform_vector(ray.dir),
+        };
+
+        self.intersect(&local).map(|h| Hit {
+            position: world.transform_point(h.position),
+            normal: inv.transpose().transform_vector(h.normal).normalize(),
+            .. h
+        })
+    }
+}
+
+/// Material values under a picked point.
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialSample {
+    pub albedo: [u8; 3], // as stored, in sRGB
+    pub roughness: f32,
+    pub metalness: f32,
+}
+
+/// CPU copies of the maps that picking reports, shrunk to at most `MATERIAL_SIZE` texels a side.
+pub struct Material {
+    pub albedo: RgbaImage,
+    pub roughness: RgbaImage,
+    pub metalness: RgbaImage,
+}
+
+/// The nearest texel, wrapping like the tiling sampler does.
+fn texel(image: &RgbaImage, uv: Point2<f32>) -> [u8; 4] {
+    let (w, h) = image.dimensions();
+    let wrap = |t: f32, size: u32| ((t - t.floor()) * size as f32) as u32 % size;
+    image.get_pixel(wrap(uv.x, w), wrap(uv.y, h)).data
+}
+
+/// Scales `image` down, keeping its aspect, until neither side is over `MATERIAL_SIZE`.
+fn shrink(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let side = w.max(h);
+    if side <= MATERIAL_SIZE { return image.clone() }
+    let scale = |s: u32| (s * MATERIAL_SIZE / side).max(1);
+    image::imageops::resize(image, scale(w), scale(h), image::FilterType::Triangle)
+}
+
+impl Material {
+    /// Keeps shrunk copies of full-size maps.
+    pub fn new(albedo: &RgbaImage, roughness: &RgbaImage, metalness: &RgbaImage) -> Material {
+        Material {
+            albedo: shrink(albedo),
+            roughness: shrink(roughness),
+            metalness: shrink(metalness),
+        }
+    }
+
+    /// A material with the same value everywhere.
+    pub fn solid(albedo: [u8; 4], roughness: [u8; 4], metalness: [u8; 4]) -> Material {
+        Material {
+            albedo: RgbaImage::from_pixel(1, 1, Rgba { data: albedo }),
+            roughness: RgbaImage::from_pixel(1, 1, Rgba { data: roughness }),
+            metalness: RgbaImage::from_pixel(1, 1, Rgba { data: metalness }),
+        }
+    }
+
+    pub fn sample(&self, uv: Point2<f32>) -> MaterialSample {
+        let albedo = texel(&self.albedo, uv);
+        MaterialSample {
+            albedo: [albedo[0], albedo[1], albedo[2]],
+            roughness: texel(&self.roughness, uv)[0] as f32 / 255.,
+            metalness: texel(&self.metalness, uv)[0] as f32 / 255.,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector3, Matrix4, vec3};
+
+    use camera::Ray;
+    use shapes;
+    use super::*;
+
+    fn near(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-4
+    }
+
+    fn cube() -> Bvh {
+        let (verts, indices) = shapes::cube(1.);
+        Bvh::new(&verts, &indices)
+    }
+
+    #[test]
+    fn intersect_cube() {
+        let bvh = cube();
+
+        // the front face, whose uvs run along x and y
+        let hit = bvh.intersect(&Ray { origin: Point3::new(0.5, 0.25, 5.), dir: vec3(0., 0., -1.) }).unwrap();
+        assert!((hit.t - 4.).abs() < 1e-4);
+        assert!(near(hit.position.to_vec(), vec3(0.5, 0.25, 1.)));
+        assert!(near(hit.normal, vec3(0., 0., 1.)));
+        assert!((hit.uv.x - 0.75).abs() < 1e-4 && (hit.uv.y - 0.625).abs() < 1e-4);
+
+        // from the inside, the back of a face still counts
+        let hit = bvh.intersect(&Ray { origin: Point3::new(0., 0., 0.), dir: vec3(1., 0., 0.) }).unwrap();
+        assert!((hit.t - 1.).abs() < 1e-4 && near(hit.normal, vec3(1., 0., 0.)));
+
+        assert!(bvh.intersect(&Ray { origin: Point3::new(2., 0., 5.), dir: vec3(0., 0., -1.) }).is_none());
+        assert!(bvh.intersect(&Ray { origin: Point3::new(0., 0., 5.), dir: vec3(0., 0., 1.) }).is_none());
+    }
+
+    #[test]
+    fn cast_placed_cube() {
+        let bvh = cube();
+        let world = Matrix4::from_translation(vec3(3., 0., 0.)) * Matrix4::from_scale(2.);
+
+        let hit = bvh.cast(&world, &Ray { origin: Point3::new(3., 1., 10.), dir: vec3(0., 0., -1.) }).unwrap();
+        assert!((hit.t - 8.).abs() < 1e-4);
+        assert!(near(hit.position.to_vec(), vec3(3., 1., 2.)));
+        assert!(near(hit.normal, vec3(0., 0., 1.)));
+
+        assert!(bvh.cast(&world, &Ray { origin: Point3::new(0., 1., 10.), dir: vec3(0., 0., -1.) }).is_none());
+        assert!(bvh.cast(&Matrix4::from_scale(0.), &Ray { origin: Point3::new(0., 0., 10.), dir: vec3(0., 0., -1.) }).is_none());
+    }
+
+    #[test]
+    fn part_keeps_its_vertices() {
+        let (verts, indices) = shapes::cube(1.);
+        let bvh = Bvh::new(&verts, &indices[..6]); // the +x face
+
+        assert_eq!((bvh.positions.len(), bvh.tris.len()), (4, 2));
+        assert!(bvh.intersect(&Ray { origin: Point3::new(5., 0., 0.), dir: vec3(-1., 0., 0.) }).is_some());
+        assert!(bvh.intersect(&Ray { origin: Point3::new(0., 0., 5.), dir: vec3(0., 0., -1.) }).is_none());
+    }
+}
@@ -0,0 +1,450 @@
+use std::str::FromStr;
+
+use define::{ExposureBlock, BloomBlock, SsaoBlock, SsrBlock, DofBlock, GradeBlock, DebugBlock};
+use camera::PhysicalCamera;
+use lut::Lut;
+use cgmath::{Vector2, vec2};
+
+/// Tone mapping operators, in the same order as the `tonemap` switch in ldr.f.glsl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMap {
+    Exponential,
+    Reinhard,
+    Aces,
+    Hable,
+    Agx,
+}
+
+pub const TONE_MAPS: [ToneMap; 5] = [
+    ToneMap::Exponential,
+    ToneMap::Reinhard,
+    ToneMap::Aces,
+    ToneMap::Hable,
+    ToneMap::Agx,
+];
+
+impl ToneMap {
+    pub fn id(self) -> i32 {
+        TONE_MAPS.iter().position(|&t| t == self).unwrap() as i32
+    }
+
+    pub fn next(self) -> ToneMap {
+        TONE_MAPS[(self.id() as usize + 1) % TONE_MAPS.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMap::Exponential => "exponential",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::Aces => "aces",
+            ToneMap::Hable => "hable",
+            ToneMap::Agx => "agx",
+        }
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        TONE_MAPS.iter()
+            .cloned()
+            .find(|t| t.name() == s)
+            .ok_or_else(|| format!("Unknown tone map \"{}\"", s))
+    }
+}
+
+/// Eye adaptation settings. EVs are log2 of the scene's average luminance.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposure {
+    pub enabled: bool,
+    pub min_ev: f32,
+    pub max_ev: f32,
+    pub compensation: f32,
+    pub speed: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> AutoExposure {
+        AutoExposure {
+            enabled: false,
+            min_ev: -10.,
+            max_ev: 10.,
+            compensation: 0.,
+            speed: 1.5,
+        }
+    }
+}
+
+impl AutoExposure {
+    pub fn to_block(&self, delta: f32) -> ExposureBlock {
+        ExposureBlock {
+            min_ev: self.min_ev,
+            max_ev: self.max_ev,
+            compensation: self.compensation,
+            speed: self.speed,
+            delta: delta,
+            enabled: if self.enabled { 1 } else { 0 },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BloomMode {
+    On,
+    Debug,
+    Off,
+}
+
+/// Settings for the threshold/downsample/upsample bloom chain.
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    pub mode: BloomMode,
+    pub threshold: f32,
+    pub knee: f32,
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+/// Number of progressively halved bloom buffers.
+pub const BLOOM_LEVELS: usize = 6;
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom {
+            mode: BloomMode::Off,
+            threshold: 10.,
+            knee: 5.,
+            radius: 1.,
+            intensity: 0.05,
+        }
+    }
+}
+
+impl Bloom {
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            BloomMode::On => BloomMode::Debug,
+            BloomMode::Debug => BloomMode::Off,
+            BloomMode::Off => BloomMode::On,
+        };
+    }
+
+    pub fn to_block(&self, prefilter: bool) -> BloomBlock {
+        BloomBlock {
+            threshold: self.threshold,
+            knee: self.knee,
+            radius: self.radius,
+            intensity: if self.mode == BloomMode::Off { 0. } else { self.intensity },
+            prefilter: if prefilter { 1 } else { 0 },
+            debug: if self.mode == BloomMode::Debug { 1 } else { 0 },
+        }
+    }
+}
+
+/// Screen-space ambient occlusion settings.
+#[derive(Clone, Copy, Debug)]
+pub struct Ssao {
+    pub enabled: bool,
+    pub radius: f32,
+    pub bias: f32,
+    pub intensity: f32,
+}
+
+impl Default for Ssao {
+    fn default() -> Ssao {
+        Ssao {
+            enabled: false,
+            radius: 0.3,
+            bias: 0.025,
+            intensity: 1.,
+        }
+    }
+}
+
+impl Ssao {
+    pub fn to_block(&self, blur_dir: [f32; 2]) -> SsaoBlock {
+        SsaoBlock {
+            blur_dir: [blur_dir[0], blur_dir[1], 0., 0.],
+            radius: self.radius,
+            bias: self.bias,
+            intensity: self.intensity,
+            enabled: if self.enabled { 1 } else { 0 },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAlias {
+    Off,
+    Fxaa,
+    Taa,
+}
+
+impl AntiAlias {
+    pub fn next(self) -> AntiAlias {
+        match self {
+            AntiAlias::Off => AntiAlias::Fxaa,
+            AntiAlias::Fxaa => AntiAlias::Taa,
+            AntiAlias::Taa => AntiAlias::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AntiAlias::Off => "off",
+            AntiAlias::Fxaa => "fxaa",
+            AntiAlias::Taa => "taa",
+        }
+    }
+}
+
+impl FromStr for AntiAlias {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<AntiAlias, String> {
+        match s {
+            "off" => Ok(AntiAlias::Off),
+            "fxaa" => Ok(AntiAlias::Fxaa),
+            "taa" => Ok(AntiAlias::Taa),
+            _ => Err(format!("Unknown anti-aliasing mode \"{}\"", s)),
+        }
+    }
+}
+
+/// Number of distinct subpixel offsets TAA cycles through.
+pub const JITTER_SAMPLES: usize = 8;
+
+fn halton(mut index: usize, base: usize) -> f32 {
+    let mut f = 1.;
+    let mut r = 0.;
+
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+
+    r
+}
+
+/// Subpixel projection offset for the given frame, in normalized device coordinates.
+pub fn jitter(frame: usize, width: u16, height: u16) -> Vector2<f32> {
+    let i = frame % JITTER_SAMPLES + 1;
+    vec2(
+        (halton(i, 2) - 0.5) * 2. / width as f32,
+        (halton(i, 3) - 0.5) * 2. / height as f32,
+    )
+}
+
+/// Screen-space reflection settings.
+#[derive(Clone, Copy, Debug)]
+pub struct Ssr {
+    pub enabled: bool,
+    pub max_dist: f32,
+    pub thickness: f32,
+    pub intensity: f32,
+}
+
+impl Default for Ssr {
+    fn default() -> Ssr {
+        Ssr {
+            enabled: false,
+            max_dist: 10.,
+            thickness: 0.002,
+            intensity: 1.,
+        }
+    }
+}
+
+impl Ssr {
+    /// `level` is the depth pyramid level being built, out of `levels`.
+    pub fn to_block(&self, level: usize, levels: usize) -> SsrBlock {
+        SsrBlock {
+            max_dist: self.max_dist,
+            thickness: self.thickness,
+            intensity: self.intensity,
+            enabled: if self.enabled { 1 } else { 0 },
+            level: level as i32,
+            levels: levels as i32,
+        }
+    }
+}
+
+/// Depth of field settings. The lens itself comes from the `PhysicalCamera`.
+#[derive(Clone, Copy, Debug)]
+pub struct Dof {
+    pub enabled: bool,
+    // screen position to focus on (0-1, origin at the bottom left), read back from the gbuffer
+    pub focus_point: Option<(f32, f32)>,
+    // focus distance in world units, used when there is no focus point
+    pub focus_dist: f32,
+}
+
+impl Default for Dof {
+    fn default() -> Dof {
+        Dof {
+            enabled: false,
+            focus_point: None,
+            focus_dist: 4.,
+        }
+    }
+}
+
+impl Dof {
+    pub fn to_block(&self, lens: &PhysicalCamera) -> DofBlock {
+        let (x, y, point) = match self.focus_point {
+            Some((x, y)) => (x, y, 1.),
+            None => (0.5, 0.5, 0.),
+        };
+
+        DofBlock {
+            focus_point: [x, y, self.focus_dist, point],
+            focal_length: lens.focal_length,
+            f_stop: lens.f_stop,
+            sensor_height: lens.sensor.1,
+            enabled: if self.enabled { 1 } else { 0 },
+        }
+    }
+}
+
+/// Color grading applied to the tone mapped image. Lift/gamma/gain are per channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Grade {
+    pub lift: [f32; 3],
+    pub gamma: [f32; 3],
+    pub gain: [f32; 3],
+    pub saturation: f32,
+    // white balance, -1 (cool/green) to 1 (warm/magenta)
+    pub temperature: f32,
+    pub tint: f32,
+    pub lut_enabled: bool,
+}
+
+impl Default for Grade {
+    fn default() -> Grade {
+        Grade {
+            lift: [0.; 3],
+            gamma: [1.; 3],
+            gain: [1.; 3],
+            saturation: 1.,
+            temperature: 0.,
+            tint: 0.,
+            lut_enabled: true,
+        }
+    }
+}
+
+// chromatic adaptation from D65 to the white point picked by temperature/tint, in LMS space
+fn white_balance(temperature: f32, tint: f32) -> [f32; 3] {
+    let x = 0.31271 - temperature * if temperature < 0. { 0.1 } else { 0.05 };
+    let y = 2.87 * x - 3. * x * x - 0.27509507 + tint * 0.05;
+
+    let (cx, cy, cz) = (x / y, 1., (1. - x - y) / y);
+    let l = 0.7328 * cx + 0.4296 * cy - 0.1624 * cz;
+    let m = -0.7036 * cx + 1.6975 * cy + 0.0061 * cz;
+    let s = 0.0030 * cx + 0.0136 * cy + 0.9834 * cz;
+
+    [0.949237 / l, 1.03542 / m, 1.08728 / s]
+}
+
+impl Grade {
+    pub fn to_block(&self, lut: &Lut) -> GradeBlock {
+        let wb = white_balance(self.temperature, self.tint);
+        let v4 = |v: [f32; 3], w: f32| [v[0], v[1], v[2], w];
+
+        GradeBlock {
+            white_balance: v4(wb, 0.),
+            lift: v4(self.lift, 0.),
+            gamma: v4(self.gamma, 1.),
+            gain: v4(self.gain, 1.),
+            domain_min: v4(lut.domain_min, 0.),
+            domain_max: v4(lut.domain_max, 1.),
+            saturation: self.saturation,
+            lut_size: lut.size as f32,
+            lut_enabled: if self.lut_enabled { 1 } else { 0 },
+        }
+    }
+}
+
+/// Intermediate buffers that can be shown instead of the final image, in the same order as the
+/// `debug_view` switch in ldr.f.glsl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    Off,
+    Position,
+    Normal,
+    Uv,
+    Albedo,
+    Roughness,
+    Metalness,
+    Shadow,
+    Light,
+    FalseColor,
+}
+
+pub const DEBUG_VIEWS: [DebugView; 10] = [
+    DebugView::Off,
+    DebugView::Position,
+    DebugView::Normal,
+    DebugView::Uv,
+    DebugView::Albedo,
+    DebugView::Roughness,
+    DebugView::Metalness,
+    DebugView::Shadow,
+    DebugView::Light,
+    DebugView::FalseColor,
+];
+
+impl DebugView {
+    pub fn id(self) -> i32 {
+        DEBUG_VIEWS.iter().position(|&v| v == self).unwrap() as i32
+    }
+
+    pub fn next(self) -> DebugView {
+        DEBUG_VIEWS[(self.id() as usize + 1) % DEBUG_VIEWS.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugView::Off => "off",
+            DebugView::Position => "position",
+            DebugView::Normal => "normal",
+            DebugView::Uv => "uv",
+            DebugView::Albedo => "albedo",
+            DebugView::Roughness => "roughness",
+            DebugView::Metalness => "metalness",
+            DebugView::Shadow => "shadow",
+            DebugView::Light => "light",
+            DebugView::FalseColor => "false_color",
+        }
+    }
+
+    /// Views that only light the scene with the selected light.
+    pub fn single_light(self) -> bool {
+        self == DebugView::Shadow || self == DebugView::Light
+    }
+
+    /// Views drawn by the debug variant of the ldr pass rather than the regular one.
+    pub fn replaces_ldr(self) -> bool {
+        self != DebugView::Off && self != DebugView::Light
+    }
+
+    pub fn to_block(self, shadow_clip: (f32, f32)) -> DebugBlock {
+        DebugBlock {
+            shadow_clip: [shadow_clip.0, shadow_clip.1, 0., 0.],
+            view: self.id(),
+        }
+    }
+}
+
+impl FromStr for DebugView {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DebugView, String> {
+        DEBUG_VIEWS.iter()
+            .cloned()
+            .find(|v| v.name() == s)
+            .ok_or_else(|| format!("Unknown debug view \"{}\"", s))
+    }
+}
@@ -0,0 +1,555 @@
+use std::io::{self, BufReader, BufRead, Write};
+use std::path::Path;
+use std::fs::File;
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Deg, PerspectiveFov, vec3};
+
+use camera::{ArcBall, BasicCamera};
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    Point,
+    Spot { inner: Deg<f32>, outer: Deg<f32> },
+}
+
+#[derive(Clone, Debug)]
+pub enum Motion {
+    Fixed(Point3<f32>),
+    // the original demo animation: spins around the origin while bobbing up and down
+    Orbit {
+        base_angle: Deg<f32>,
+        speed: f32,
+        wobble_speed: f32,
+        wobble: f32,
+        dist: f32,
+        center: Point3<f32>,
+    },
+    // (time, position) keys, linearly interpolated and looped
+    Path(Vec<(f32, Point3<f32>)>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Shadow {
+    pub enabled: bool,
+    pub fov: Deg<f32>,
+    pub bias: f32,
+    pub clip: (f32, f32), // near and far, fitted to the scene by `Light::fit_orbit` and not saved
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    pub color: [f32; 4], // rgb + intensity
+    pub ambient: [f32; 4],
+    pub motion: Motion,
+    pub target: Point3<f32>,
+    pub shadow: Shadow,
+}
+
+#[derive(Clone, Debug)]
+pub struct Rig {
+    pub lights: Vec<Light>,
+    pub shadow_size: u16,
+}
+
+impl Default for Shadow {
+    fn default() -> Shadow {
+        Shadow {
+            enabled: true,
+            fov: Deg(20.),
+            bias: 0.,
+            clip: (0.1, 100.),
+        }
+    }
+}
+
+impl Motion {
+    pub fn position(&self, time: f32) -> Point3<f32> {
+        match *self {
+            Motion::Fixed(p) => p,
+            Motion::Orbit { base_angle, speed, wobble_speed, wobble, dist, center } => {
+                let theta = base_angle + Deg(time * speed);
+                let arc = ArcBall {
+                    origin: center,
+                    theta: theta,
+                    phi: Deg((theta + Deg(time * wobble_speed)).sin() * wobble),
+                    dist: dist,
+                    projection: unit_projection(Deg(20.)),
+                };
+
+                arc.to_camera().eye
+            },
+            Motion::Path(ref keys) => {
+                let len = keys.last().map(|k| k.0).unwrap_or(0.);
+                let time = if len > 0. { time % len } else { 0. };
+
+                match keys.iter().position(|k| k.0 > time) {
+                    Some(0) => keys[0].1,
+                    Some(i) => {
+                        let (t0, p0) = keys[i - 1];
+                        let (t1, p1) = keys[i];
+                        p0 + (p1 - p0) * ((time - t0) / (t1 - t0))
+                    },
+                    None => keys.last().map(|k| k.1).unwrap_or(Point3::new(0., 0., 0.)),
+                }
+            },
+        }
+    }
+
+    /// The closest and farthest the light ever gets to `p`.
+    pub fn distance_range(&self, p: Point3<f32>) -> (f32, f32) {
+        match *self {
+            Motion::Fixed(q) => {
+                let d = q.distance(p);
+                (d, d)
+            },
+            // the orbit keeps its distance from the center, whatever the wobble does
+            Motion::Orbit { dist, center, .. } => {
+                let d = center.distance(p);
+                ((d - dist).abs(), d + dist)
+            },
+            Motion::Path(ref keys) => {
+                let far = keys.iter().map(|k| k.1.distance(p)).fold(0., f32::max);
+                let near = keys.windows(2)
+                    .map(|w| {
+                        let (a, b) = (w[0].1, w[1].1);
+                        let t = if a == b { 0. } else { (p - a).dot(b - a) / (b - a).magnitude2() };
+                        (a + (b - a) * t.max(0.).min(1.)).distance(p)
+                    })
+                    .fold(keys.first().map(|k| k.1.distance(p)).unwrap_or(0.), f32::min);
+                (near, far)
+            },
+        }
+    }
+}
+
+/// The size of object the default orbit (and camera distance) was made for.
+pub const ORBIT_FIT_RADIUS: f32 = 1.5;
+
+fn unit_projection(fov: Deg<f32>) -> PerspectiveFov<f32> {
+    PerspectiveFov {
+        fovy: fov.into(),
+        aspect: 1.,
+        near: 0.1, far: 100.
+    }
+}
+
+impl Light {
+    pub fn position(&self, time: f32) -> Point3<f32> {
+        self.motion.position(time)
+    }
+
+    pub fn direction(&self, time: f32) -> ::cgmath::Vector3<f32> {
+        (self.target - self.position(time)).normalize()
+    }
+
+    /// The camera used to render this light's shadow map.
+    pub fn shadow_camera(&self, time: f32) -> BasicCamera<PerspectiveFov<f32>> {
+        let eye = self.position(time);
+        let up = if self.direction(time).y.abs() > 0.99 { vec3(0., 0., 1.) } else { vec3(0., 1., 0.) };
+
+        BasicCamera {
+            eye: eye,
+            target: self.target,
+            up: up,
+            projection: PerspectiveFov {
+                fovy: self.shadow.fov.into(),
+                aspect: 1.,
+                near: self.shadow.clip.0,
+                far: self.shadow.clip.1,
+            },
+        }
+    }
+
+    /// Cosines of the inner and outer cone angles. Point lights get values that never cut off.
+    pub fn cone(&self) -> [f32; 4] {
+        match self.kind {
+            LightKind::Point => [-2., -3., 0., 0.],
+            LightKind::Spot { inner, outer } => [inner.cos(), outer.cos(), 0., 0.],
+        }
+    }
+
+    pub fn shadow_params(&self) -> [f32; 4] {
+        [if self.shadow.enabled { 1. } else { 0. }, self.shadow.bias, 0., 0.]
+    }
+
+    /// Moves an orbiting light (as `base` placed it) to circle an object of the given center and
+    /// radius, or back to where `base` had it. Other lights keep their place. Either way the shadow
+    /// depth range is fitted to the same sphere.
+    pub fn fit_orbit(&mut self, base: &Light, fit: Option<(Point3<f32>, f32)>) {
+        if let (&mut Motion::Orbit { ref mut dist, ref mut center, .. },
+                &Motion::Orbit { dist: base_dist, center: base_center, .. }) = (&mut self.motion, &base.motion)
+        {
+            let (new_center, radius) = fit.unwrap_or((base_center, ORBIT_FIT_RADIUS));
+
+            self.target = new_center + (base.target - base_center);
+            *dist = base_dist * radius / ORBIT_FIT_RADIUS;
+            *center = new_center;
+        }
+
+        self.shadow.clip = match fit {
+            Some((center, radius)) => {
+                let (closest, farthest) = self.motion.distance_range(center);
+                let far = farthest + radius;
+                // a light inside the sphere still needs some near plane, keep it within depth precision
+                ((closest - radius).max(far / 1000.), far)
+            },
+            None => base.shadow.clip,
+        };
+    }
+
+    /// The color, with the intensity following the orbit distance so a fitted object stays as bright.
+    pub fn fitted_color(&self, base: &Light) -> [f32; 4] {
+        let mut color = self.color;
+        if let (&Motion::Orbit { dist, .. }, &Motion::Orbit { dist: base_dist, .. }) = (&self.motion, &base.motion) {
+            color[3] *= (dist / base_dist).powi(2);
+        }
+        color
+    }
+}
+
+impl Rig {
+    /// `count` lights evenly spaced around the orbit the demo has always used.
+    pub fn orbit(count: usize, mut color: [f32; 4], mut ambient: [f32; 4]) -> Rig {
+        ambient[3] *= 1.5 / count as f32;
+        color[3] *= 250. / count as f32;
+
+        let lights = (0..count)
+            .map(|i| Light {
+                name: format!("orbit{}", i),
+                kind: LightKind::Point,
+                color: color,
+                ambient: ambient,
+                motion: Motion::Orbit {
+                    base_angle: Deg(i as f32 * 360. / count as f32),
+                    speed: 30.,
+                    wobble_speed: 13.,
+                    wobble: 45.,
+                    dist: 7.,
+                    center: Point3::new(0., 0., 0.),
+                },
+                target: Point3::new(0., 0., 0.),
+                shadow: Shadow::default(),
+            }).collect();
+
+        Rig {
+            lights: lights,
+            shadow_size: 512,
+        }
+    }
+}
+
+/// Parses a "rrggbb" hex color into linear-ish 0-1 components.
+pub fn parse_hex(c: &str) -> Option<[f32; 3]> {
+    if c.len() != 6 { return None }
+    let z = match u64::from_str_radix(c, 16) {
+        Ok(z) => z,
+        Err(_) => return None,
+    };
+    Some([
+        ((z >> 16) & 0xFF) as f32 / 255.,
+        ((z >> 8 ) & 0xFF) as f32 / 255.,
+        ( z        & 0xFF) as f32 / 255.,
+    ])
+}
+
+pub fn to_hex(c: [f32; 4]) -> String {
+    let b = |v: f32| (v.max(0.).min(1.) * 255. + 0.5) as u8;
+    format!("{:02x}{:02x}{:02x}", b(c[0]), b(c[1]), b(c[2]))
+}
+
+//
+// Rig files are parsed line by line, just like wavefront files. Every "light" line starts a new
+// light and the lines after it modify that light. See rigs/ for examples.
+//
+
+fn next_f32<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<f32, usize> {
+    line.next().ok_or(linen)?.parse().or(Err(linen))
+}
+
+/// Like `next_f32`, but a missing value gives `default`. Malformed values are still errors.
+fn opt_f32<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize, default: f32) -> Result<f32, usize> {
+    match line.next() {
+        Some(v) => v.parse().or(Err(linen)),
+        None => Ok(default),
+    }
+}
+
+fn next_point<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<Point3<f32>, usize> {
+    Ok(Point3::new(next_f32(line, linen)?, next_f32(line, linen)?, next_f32(line, linen)?))
+}
+
+fn next_color<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<[f32; 4], usize> {
+    let rgb = parse_hex(line.next().ok_or(linen)?).ok_or(linen)?;
+    let i = match line.next() {
+        Some(v) => v.parse().or(Err(linen))?,
+        None => 1.,
+    };
+    Ok([rgb[0], rgb[1], rgb[2], i])
+}
+
+/// Handles a single rig directive. Returns `Ok(false)` if the keyword is not a rig keyword, so
+/// that other file formats can embed rig directives.
+pub fn parse_rig_line<'a, I>(key: &str, line: &mut I, linen: usize, rig: &mut Rig, ambient: &mut [f32; 4])
+    -> Result<bool, usize>
+    where I: Iterator<Item = &'a str>
+{
+    match key {
+        "light" => {
+            rig.lights.push(Light {
+                name: line.next().unwrap_or("").to_owned(),
+                kind: LightKind::Point,
+                color: [1., 1., 1., 50.],
+                ambient: [0.; 4],
+                motion: Motion::Fixed(Point3::new(0., 5., 0.)),
+                target: Point3::new(0., 0., 0.),
+                shadow: Shadow::default(),
+            });
+            return Ok(true);
+        },
+        "ambient" => {
+            *ambient = next_color(line, linen)?;
+            return Ok(true);
+        },
+        "shadow_map" => {
+            rig.shadow_size = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+            return Ok(true);
+        },
+        "type" | "color" | "pos" | "orbit" | "key" | "target" | "shadow" => (),
+        _ => return Ok(false),
+    }
+
+    let light = rig.lights.last_mut().ok_or(linen)?;
+
+    match key {
+        "type" => {
+            light.kind = match line.next() {
+                Some("point") => LightKind::Point,
+                Some("spot") => {
+                    let inner = next_f32(line, linen)?;
+                    let outer = next_f32(line, linen)?;
+                    LightKind::Spot { inner: Deg(inner), outer: Deg(outer) }
+                },
+                _ => return Err(linen),
+            }
+        },
+        "color" => light.color = next_color(line, linen)?,
+        "pos" => light.motion = Motion::Fixed(next_point(line, linen)?),
+        "orbit" => {
+            light.motion = Motion::Orbit {
+                base_angle: Deg(next_f32(line, linen)?),
+                speed: opt_f32(line, linen, 30.)?,
+                wobble_speed: opt_f32(line, linen, 13.)?,
+                wobble: opt_f32(line, linen, 45.)?,
+                dist: opt_f32(line, linen, 7.)?,
+                center: Point3::new(opt_f32(line, linen, 0.)?, opt_f32(line, linen, 0.)?, opt_f32(line, linen, 0.)?),
+            }
+        },
+        "key" => {
+            let key = (next_f32(line, linen)?, next_point(line, linen)?);
+            let has_path = if let Motion::Path(_) = light.motion { true } else { false };
+            if !has_path { light.motion = Motion::Path(Vec::new()) }
+
+            if let Motion::Path(ref mut keys) = light.motion {
+                if keys.last().map(|k| k.0 >= key.0).unwrap_or(false) { return Err(linen) }
+                keys.push(key);
+            }
+        },
+        "target" => light.target = next_point(line, linen)?,
+        "shadow" => {
+            match line.next() {
+                Some("off") => light.shadow.enabled = false,
+                Some(fov) => {
+                    light.shadow.enabled = true;
+                    light.shadow.fov = Deg(fov.parse().or(Err(linen))?);
+                    light.shadow.bias = opt_f32(line, linen, 0.)?;
+                },
+                None => return Err(linen),
+            }
+        },
+        _ => unreachable!(),
+    }
+
+    Ok(true)
+}
+
+/// Spreads the rig-wide ambient term over the lights, since each light pass adds its own.
+pub fn finish_rig(rig: &mut Rig, ambient: [f32; 4]) {
+    let count = rig.lights.len() as f32;
+    for l in &mut rig.lights {
+        l.ambient = ambient;
+        l.ambient[3] /= count;
+    }
+}
+
+pub fn load_rig<R: BufRead>(read: R) -> Result<Rig, usize> {
+    let mut rig = Rig {
+        lights: Vec::new(),
+        shadow_size: 512,
+    };
+    let mut ambient = [0.; 4];
+
+    let mut linen = 0usize;
+
+    for line in read.lines() {
+        linen += 1;
+
+        let line = line.or(Err(linen))?;
+        let mut line = line.split('#').next().unwrap_or("").split_whitespace();
+
+        match line.next() {
+            Some(key) => if !parse_rig_line(key, &mut line, linen, &mut rig, &mut ambient)? {
+                return Err(linen);
+            },
+            None => (),
+        }
+    }
+
+    finish_rig(&mut rig, ambient);
+    Ok(rig)
+}
+
+pub fn open_rig<P: AsRef<Path>>(path: P) -> Result<Rig, String> {
+    let display = format!("{}", path.as_ref().display());
+
+    match File::open(path) {
+        Ok(f) => match load_rig(BufReader::new(f)) {
+            Ok(rig) => Ok(rig),
+            Err(line) => Err(format!("Error parsing \"{}\" on line #{}", display, line)),
+        },
+        Err(e) => Err(format!("File \"{}\" could not be opened: {:?}", display, e)),
+    }
+}
+
+/// Writes the rig in the same format `load_rig` reads.
+pub fn write_rig<W: Write>(rig: &Rig, w: &mut W) -> io::Result<()> {
+    let count = rig.lights.len() as f32;
+    if let Some(l) = rig.lights.first() {
+        writeln!(w, "ambient {} {}", to_hex(l.ambient), l.ambient[3] * count)?;
+    }
+    writeln!(w, "shadow_map {}", rig.shadow_size)?;
+
+    for l in &rig.lights {
+        writeln!(w, "\nlight {}", l.name)?;
+        match l.kind {
+            LightKind::Point => writeln!(w, "type point")?,
+            LightKind::Spot { inner, outer } => writeln!(w, "type spot {} {}", inner.0, outer.0)?,
+        }
+        writeln!(w, "color {} {}", to_hex(l.color), l.color[3])?;
+
+        match l.motion {
+            Motion::Fixed(p) => writeln!(w, "pos {} {} {}", p.x, p.y, p.z)?,
+            Motion::Orbit { base_angle, speed, wobble_speed, wobble, dist, center } =>
+                writeln!(w, "orbit {} {} {} {} {} {} {} {}",
+                    base_angle.0, speed, wobble_speed, wobble, dist, center.x, center.y, center.z)?,
+            Motion::Path(ref keys) => for &(t, p) in keys {
+                writeln!(w, "key {} {} {} {}", t, p.x, p.y, p.z)?;
+            },
+        }
+
+        writeln!(w, "target {} {} {}", l.target.x, l.target.y, l.target.z)?;
+        if l.shadow.enabled {
+            writeln!(w, "shadow {} {}", l.shadow.fov.0, l.shadow.bias)?;
+        } else {
+            writeln!(w, "shadow off")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Deg};
+
+    use super::*;
+
+    const RIG: &'static str = "\
+ambient 4d479b 0.5
+shadow_map 1024
+
+light key
+type spot 20 30
+color ffffff 80
+pos 4 5 6
+target 0 1 0
+shadow 45 0.001
+
+light sweep
+key 0 -6 3 0
+key 4 6 3 0
+shadow off
+
+light orbit # defaults after the angle
+orbit 90
+";
+
+    #[test]
+    fn load() {
+        let rig = load_rig(RIG.as_bytes()).unwrap();
+        assert_eq!(rig.shadow_size, 1024);
+        assert_eq!(rig.lights.len(), 3);
+
+        let key = &rig.lights[0];
+        assert_eq!(key.name, "key");
+        assert_eq!(format!("{:?}", key.kind), format!("{:?}", LightKind::Spot { inner: Deg(20.), outer: Deg(30.) }));
+        assert_eq!(key.color, [1., 1., 1., 80.]);
+        assert_eq!(key.position(10.), Point3::new(4., 5., 6.));
+        assert_eq!(key.target, Point3::new(0., 1., 0.));
+        assert!(key.shadow.enabled && key.shadow.fov == Deg(45.) && key.shadow.bias == 0.001);
+        // the ambient is spread over the lights
+        assert_eq!(key.ambient[3], 0.5 / 3.);
+
+        let sweep = &rig.lights[1];
+        assert!(!sweep.shadow.enabled);
+        assert_eq!(sweep.position(2.), Point3::new(0., 3., 0.));
+
+        match rig.lights[2].motion {
+            Motion::Orbit { base_angle, speed, dist, center, .. } =>
+                assert!(base_angle == Deg(90.) && speed == 30. && dist == 7. && center == Point3::new(0., 0., 0.)),
+            ref m => panic!("{:?} should be an orbit", m),
+        }
+    }
+
+    #[test]
+    fn reject() {
+        let line = |text: &str| load_rig(text.as_bytes()).unwrap_err();
+
+        assert_eq!(line("color ffffff 1"), 1); // no light yet
+        assert_eq!(line("light a\nspin 3"), 2);
+        assert_eq!(line("light a\ncolor fffff"), 2);
+        assert_eq!(line("light a\ntype spot 20"), 2);
+        assert_eq!(line("light a\norbit 0 fast"), 2);
+        assert_eq!(line("light a\nkey 1 0 0 0\nkey 1 1 0 0"), 3); // keys have to move forward
+        assert_eq!(line("light a\nshadow"), 2);
+        assert_eq!(line("shadow_map -1"), 1);
+    }
+
+    #[test]
+    fn fitted_shadow_clip() {
+        let rig = load_rig(RIG.as_bytes()).unwrap();
+        let center = Point3::new(0., 0., 0.);
+
+        // the orbit grows with the sphere, the shadow range covers the sphere from there
+        let mut orbit = rig.lights[2].clone();
+        orbit.fit_orbit(&rig.lights[2], Some((center, 3.)));
+        let dist = 7. * 3. / ORBIT_FIT_RADIUS;
+        assert!((orbit.shadow.clip.0 - (dist - 3.)).abs() < 1e-4 && (orbit.shadow.clip.1 - (dist + 3.)).abs() < 1e-4);
+
+        // the sweep passes 3 above the center between its keys
+        let mut sweep = rig.lights[1].clone();
+        sweep.fit_orbit(&rig.lights[1], Some((center, 1.)));
+        assert!((sweep.shadow.clip.0 - 2.).abs() < 1e-4);
+        assert!((sweep.shadow.clip.1 - (45f32.sqrt() + 1.)).abs() < 1e-4);
+
+        // a light inside the sphere keeps a usable near plane
+        let mut key = rig.lights[0].clone();
+        key.fit_orbit(&rig.lights[0], Some((center, 10.)));
+        assert!(key.shadow.clip.0 > 0. && key.shadow.clip.1 > 10.);
+
+        key.fit_orbit(&rig.lights[0], None);
+        assert_eq!(key.shadow.clip, Shadow::default().clip);
+    }
+}
@@ -0,0 +1,643 @@
+use std::io::{self, BufReader, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::fs::File;
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3, Deg, vec3};
+
+use camera::PhysicalCamera;
+use post::{ToneMap, AutoExposure, Bloom, BloomMode, Ssao, Ssr, AntiAlias, Dof, Grade};
+use rig::{self, Rig};
+use graph::Trs;
+use shapes::Shape;
+
+/// An object directory (model.obj + textures) and where to put it.
+#[derive(Clone, Debug)]
+pub struct SceneObject {
+    pub dir: PathBuf,
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>, // degrees around x, then y, then z
+    pub scale: f32,
+    pub normalize: Option<f32>, // recenter the model and scale it to this bounding radius
+    pub parts: Vec<ScenePart>,
+    pub instances: Vec<SceneInstance>, // drawn once per instance if there are any
+    pub shape: Option<Shape>, // drawn instead of model.obj, with the directory's textures
+}
+
+impl SceneObject {
+    pub fn new(dir: PathBuf) -> SceneObject {
+        SceneObject {
+            dir: dir,
+            position: vec3(0., 0., 0.),
+            rotation: vec3(0., 0., 0.),
+            scale: 1.,
+            normalize: None,
+            parts: Vec::new(),
+            instances: Vec::new(),
+            shape: None,
+        }
+    }
+
+    pub fn trs(&self) -> Trs {
+        Trs::from_euler(self.position, self.rotation, self.scale)
+    }
+}
+
+/// Moves one `o`/`g` group of an object's model. The group rotates and scales around the center
+/// of its bounds, and the position is an offset from where the model put it.
+#[derive(Clone, Debug)]
+pub struct ScenePart {
+    pub name: String,
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: f32,
+}
+
+impl ScenePart {
+    pub fn trs(&self) -> Trs {
+        Trs::from_euler(self.position, self.rotation, self.scale)
+    }
+}
+
+/// One copy of an object, placed relative to the object's own position, rotation and scale.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneInstance {
+    pub position: Vector3<f32>,
+    pub rotation: Vector3<f32>,
+    pub scale: f32,
+}
+
+impl SceneInstance {
+    pub fn trs(&self) -> Trs {
+        Trs::from_euler(self.position, self.rotation, self.scale)
+    }
+}
+
+/// Spheres sweeping roughness from 0 to 1 along x against metalness from 0 to 1 along y,
+/// centered on the origin.
+#[derive(Clone, Copy, Debug)]
+pub struct Board {
+    pub size: usize, // spheres on each side
+    pub spacing: f32,
+    pub albedo: [f32; 3],
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board {
+            size: 7,
+            spacing: 1.,
+            albedo: rig::parse_hex(DEFAULT_BOARD_ALBEDO).unwrap(),
+        }
+    }
+}
+
+/// Where the arc-ball camera sits.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPose {
+    pub origin: Point3<f32>,
+    pub theta: Deg<f32>,
+    pub phi: Deg<f32>,
+    pub dist: f32,
+}
+
+impl Default for CameraPose {
+    fn default() -> CameraPose {
+        CameraPose {
+            origin: Point3::new(0., 0., 0.),
+            theta: Deg(45.),
+            phi: Deg(35.264),
+            dist: 4.,
+        }
+    }
+}
+
+/// Everything needed to reproduce a render: objects, lights, camera and post processing.
+#[derive(Clone, Debug)]
+pub struct Scene {
+    pub objects: Vec<SceneObject>,
+    pub board: Option<Board>,
+    pub ground: Option<f32>,
+    pub rig: Rig,
+    pub camera: Option<CameraPose>, // None orbits the first object
+    pub lens: PhysicalCamera,
+    pub dof: Dof,
+    pub gamma: f32,
+    pub tonemap: ToneMap,
+    pub auto_exposure: AutoExposure,
+    pub bloom: Bloom,
+    pub ssao: Ssao,
+    pub ssr: Ssr,
+    pub aa: AntiAlias,
+    pub grade: Grade,
+    pub lut: Option<PathBuf>,
+}
+
+pub const DEFAULT_LIGHT_COLOR: &'static str = "e0bd91";
+pub const DEFAULT_AMBIENT: &'static str = "4d479b";
+pub const DEFAULT_BOARD_ALBEDO: &'static str = "d8d0c8";
+
+impl Default for Scene {
+    fn default() -> Scene {
+        let color = rig::parse_hex(DEFAULT_LIGHT_COLOR).unwrap();
+        let ambient = rig::parse_hex(DEFAULT_AMBIENT).unwrap();
+
+        Scene {
+            objects: Vec::new(),
+            board: None,
+            ground: None,
+            rig: Rig::orbit(5, [color[0], color[1], color[2], 1.], [ambient[0], ambient[1], ambient[2], 1.]),
+            camera: None,
+            lens: PhysicalCamera::default(),
+            dof: Dof::default(),
+            gamma: 2.2,
+            tonemap: ToneMap::Exponential,
+            auto_exposure: AutoExposure::default(),
+            bloom: Bloom::default(),
+            ssao: Ssao::default(),
+            ssr: Ssr::default(),
+            aa: AntiAlias::Off,
+            grade: Grade::default(),
+            lut: None,
+        }
+    }
+}
+
+//
+// Scene files are parsed line by line, like rig files. Any rig directive is allowed and the rest
+// set up objects, the camera and post processing. See scenes/ for an example.
+//
+
+fn next_f32<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<f32, usize> {
+    line.next().ok_or(linen)?.parse().or(Err(linen))
+}
+
+fn next_vec3<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<Vector3<f32>, usize> {
+    Ok(vec3(next_f32(line, linen)?, next_f32(line, linen)?, next_f32(line, linen)?))
+}
+
+fn next_rgb<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<[f32; 3], usize> {
+    let v = next_vec3(line, linen)?;
+    Ok([v.x, v.y, v.z])
+}
+
+fn next_switch<'a, I: Iterator<Item = &'a str>>(line: &mut I, linen: usize) -> Result<bool, usize> {
+    match line.next() {
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        _ => Err(linen),
+    }
+}
+
+fn parse_scene_line<'a, I>(key: &str, line: &mut I, linen: usize, scene: &mut Scene) -> Result<bool, usize>
+    where I: Iterator<Item = &'a str>
+{
+    match key {
+        "object" => {
+            let dir = line.next().ok_or(linen)?;
+            scene.objects.push(SceneObject::new(PathBuf::from(dir)));
+        },
+        "normalize" => {
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            object.normalize = match line.next() {
+                Some("off") => None,
+                Some(r) => Some(r.parse().or(Err(linen))?),
+                None => Some(1.),
+            };
+        },
+        "shape" => {
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            object.shape = match line.next().ok_or(linen)? {
+                "off" => None,
+                s => Some(s.parse().or(Err(linen))?),
+            };
+        },
+        "board" => {
+            let mut board = Board::default();
+            if let Some(n) = line.next() {
+                board.size = n.parse().or(Err(linen))?;
+                if board.size == 0 { return Err(linen) }
+            }
+            if let Some(s) = line.next() {
+                board.spacing = s.parse().or(Err(linen))?;
+            }
+            if let Some(c) = line.next() {
+                board.albedo = rig::parse_hex(c).ok_or(linen)?;
+            }
+            scene.board = Some(board);
+        },
+        "part" => {
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            object.parts.push(ScenePart {
+                name: line.collect::<Vec<_>>().join(" "),
+                position: vec3(0., 0., 0.),
+                rotation: vec3(0., 0., 0.),
+                scale: 1.,
+            });
+        },
+        "instance" => {
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            let position = next_vec3(line, linen)?;
+            let (rotation, scale) = match line.next() {
+                Some(x) => {
+                    let rotation = vec3(x.parse().or(Err(linen))?, next_f32(line, linen)?, next_f32(line, linen)?);
+                    (rotation, match line.next() {
+                        Some(s) => s.parse().or(Err(linen))?,
+                        None => 1.,
+                    })
+                },
+                None => (vec3(0., 0., 0.), 1.),
+            };
+            object.instances.push(SceneInstance {
+                position: position,
+                rotation: rotation,
+                scale: scale,
+            });
+        },
+        "grid" => {
+            // columns along x and rows along y, centered on the object
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            let columns: usize = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+            let rows: usize = line.next().ok_or(linen)?.parse().or(Err(linen))?;
+            let spacing = next_f32(line, linen)?;
+            if columns == 0 || rows == 0 { return Err(linen) }
+            for row in 0..rows {
+                for column in 0..columns {
+                    object.instances.push(SceneInstance {
+                        position: vec3(
+                            (column as f32 - (columns - 1) as f32 * 0.5) * spacing,
+                            (row as f32 - (rows - 1) as f32 * 0.5) * spacing,
+                            0.),
+                        rotation: vec3(0., 0., 0.),
+                        scale: 1.,
+                    });
+                }
+            }
+        },
+        "position" | "rotation" | "scale" => {
+            let object = scene.objects.last_mut().ok_or(linen)?;
+            // after a part line, these apply to the part instead of the whole object
+            let (position, rotation, scale) = match object.parts.last_mut() {
+                Some(p) => (&mut p.position, &mut p.rotation, &mut p.scale),
+                None => (&mut object.position, &mut object.rotation, &mut object.scale),
+            };
+            match key {
+                "position" => *position = next_vec3(line, linen)?,
+                "rotation" => *rotation = next_vec3(line, linen)?,
+                _ => *scale = next_f32(line, linen)?,
+            }
+        },
+        "ground" => {
+            scene.ground = match line.next() {
+                Some("off") => None,
+                Some(h) => Some(h.parse().or(Err(linen))?),
+                None => return Err(linen),
+            }
+        },
+        "camera" => {
+            let mut pose = scene.camera.unwrap_or(CameraPose::default());
+            match line.next() {
+                Some("origin") => pose.origin = Point3::from_vec(next_vec3(line, linen)?),
+                Some("orbit") => {
+                    pose.theta = Deg(next_f32(line, linen)?);
+                    pose.phi = Deg(next_f32(line, linen)?);
+                    pose.dist = next_f32(line, linen)?;
+                },
+                _ => return Err(linen),
+            }
+            scene.camera = Some(pose);
+        },
+        "lens" => {
+            scene.lens.focal_length = next_f32(line, linen)?;
+            scene.lens.f_stop = next_f32(line, linen)?;
+            scene.lens.shutter = next_f32(line, linen)?;
+            scene.lens.iso = next_f32(line, linen)?;
+        },
+        "dof" => scene.dof.enabled = next_switch(line, linen)?,
+        "focus" => {
+            match line.next() {
+                Some("point") => scene.dof.focus_point = Some((next_f32(line, linen)?, next_f32(line, linen)?)),
+                Some(d) => {
+                    scene.dof.focus_point = None;
+                    scene.dof.focus_dist = d.parse().or(Err(linen))?;
+                },
+                None => return Err(linen),
+            }
+        },
+        "gamma" => scene.gamma = next_f32(line, linen)?,
+        "tonemap" => scene.tonemap = line.next().ok_or(linen)?.parse().or(Err(linen))?,
+        "exposure" => {
+            scene.auto_exposure.enabled = match line.next() {
+                Some("manual") => false,
+                Some("auto") => true,
+                _ => return Err(linen),
+            };
+            // kept while manual too, so toggling auto exposure back on finds them again
+            if let Some(c) = line.next() {
+                scene.auto_exposure.compensation = c.parse().or(Err(linen))?;
+                scene.auto_exposure.min_ev = next_f32(line, linen)?;
+                scene.auto_exposure.max_ev = next_f32(line, linen)?;
+            }
+        },
+        "bloom" => {
+            scene.bloom.mode = match line.next() {
+                Some("on") => BloomMode::On,
+                Some("debug") => BloomMode::Debug,
+                Some("off") => BloomMode::Off,
+                _ => return Err(linen),
+            };
+            if let Some(i) = line.next() {
+                scene.bloom.intensity = i.parse().or(Err(linen))?;
+                scene.bloom.radius = next_f32(line, linen)?;
+                scene.bloom.threshold = next_f32(line, linen)?;
+                scene.bloom.knee = scene.bloom.threshold * 0.5;
+            }
+        },
+        "ssao" => {
+            scene.ssao.enabled = next_switch(line, linen)?;
+            if let Some(r) = line.next() {
+                scene.ssao.radius = r.parse().or(Err(linen))?;
+                scene.ssao.intensity = next_f32(line, linen)?;
+            }
+        },
+        "ssr" => {
+            scene.ssr.enabled = next_switch(line, linen)?;
+            if let Some(i) = line.next() {
+                scene.ssr.intensity = i.parse().or(Err(linen))?;
+            }
+        },
+        "aa" => scene.aa = line.next().ok_or(linen)?.parse().or(Err(linen))?,
+        "lut" => {
+            scene.lut = Some(PathBuf::from(line.next().ok_or(linen)?));
+            scene.grade.lut_enabled = match line.next() {
+                Some("off") => false,
+                Some("on") | None => true,
+                _ => return Err(linen),
+            };
+        },
+        "lift" => scene.grade.lift = next_rgb(line, linen)?,
+        "grade_gamma" => scene.grade.gamma = next_rgb(line, linen)?,
+        "gain" => scene.grade.gain = next_rgb(line, linen)?,
+        "saturation" => scene.grade.saturation = next_f32(line, linen)?,
+        "white_balance" => {
+            scene.grade.temperature = next_f32(line, linen)?;
+            scene.grade.tint = next_f32(line, linen)?;
+        },
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+pub fn load_scene<R: BufRead>(read: R) -> Result<Scene, usize> {
+    let mut scene = Scene::default();
+    let mut rig = Rig {
+        lights: Vec::new(),
+        shadow_size: 512,
+    };
+    let mut ambient = [0.; 4];
+
+    let mut linen = 0usize;
+
+    for line in read.lines() {
+        linen += 1;
+
+        let line = line.or(Err(linen))?;
+        let mut line = line.split('#').next().unwrap_or("").split_whitespace();
+
+        match line.next() {
+            Some(key) => {
+                if !parse_scene_line(key, &mut line, linen, &mut scene)?
+                    && !rig::parse_rig_line(key, &mut line, linen, &mut rig, &mut ambient)?
+                {
+                    return Err(linen);
+                }
+            },
+            None => (),
+        }
+    }
+
+    // scenes without lights keep the default orbiting ones
+    if !rig.lights.is_empty() {
+        rig::finish_rig(&mut rig, ambient);
+        scene.rig = rig;
+    }
+
+    Ok(scene)
+}
+
+pub fn open_scene<P: AsRef<Path>>(path: P) -> Result<Scene, String> {
+    let display = format!("{}", path.as_ref().display());
+
+    match File::open(path) {
+        Ok(f) => match load_scene(BufReader::new(f)) {
+            Ok(scene) => Ok(scene),
+            Err(line) => Err(format!("Error parsing \"{}\" on line #{}", display, line)),
+        },
+        Err(e) => Err(format!("File \"{}\" could not be opened: {:?}", display, e)),
+    }
+}
+
+fn on_off(b: bool) -> &'static str {
+    if b { "on" } else { "off" }
+}
+
+pub fn write_scene<W: Write>(scene: &Scene, w: &mut W) -> io::Result<()> {
+    writeln!(w, "# objects")?;
+    for o in &scene.objects {
+        writeln!(w, "object {}", o.dir.display())?;
+        writeln!(w, "position {} {} {}", o.position.x, o.position.y, o.position.z)?;
+        writeln!(w, "rotation {} {} {}", o.rotation.x, o.rotation.y, o.rotation.z)?;
+        writeln!(w, "scale {}", o.scale)?;
+        if let Some(r) = o.normalize {
+            writeln!(w, "normalize {}", r)?;
+        }
+        if let Some(s) = o.shape {
+            writeln!(w, "shape {}", s.name())?;
+        }
+        for i in &o.instances {
+            writeln!(w, "instance {} {} {} {} {} {} {}",
+                i.position.x, i.position.y, i.position.z, i.rotation.x, i.rotation.y, i.rotation.z, i.scale)?;
+        }
+        for p in &o.parts {
+            writeln!(w, "part {}", p.name)?;
+            writeln!(w, "position {} {} {}", p.position.x, p.position.y, p.position.z)?;
+            writeln!(w, "rotation {} {} {}", p.rotation.x, p.rotation.y, p.rotation.z)?;
+            writeln!(w, "scale {}", p.scale)?;
+        }
+    }
+    if let Some(b) = scene.board {
+        let a = b.albedo;
+        writeln!(w, "board {} {} {}", b.size, b.spacing, rig::to_hex([a[0], a[1], a[2], 1.]))?;
+    }
+    match scene.ground {
+        Some(h) => writeln!(w, "ground {}", h)?,
+        None => writeln!(w, "ground off")?,
+    }
+
+    writeln!(w, "\n# camera")?;
+    if let Some(c) = scene.camera {
+        writeln!(w, "camera origin {} {} {}", c.origin.x, c.origin.y, c.origin.z)?;
+        writeln!(w, "camera orbit {} {} {}", c.theta.0, c.phi.0, c.dist)?;
+    }
+    let l = &scene.lens;
+    writeln!(w, "lens {} {} {} {}", l.focal_length, l.f_stop, l.shutter, l.iso)?;
+    writeln!(w, "dof {}", on_off(scene.dof.enabled))?;
+    match scene.dof.focus_point {
+        Some((x, y)) => writeln!(w, "focus point {} {}", x, y)?,
+        None => writeln!(w, "focus {}", scene.dof.focus_dist)?,
+    }
+
+    writeln!(w, "\n# post processing")?;
+    writeln!(w, "gamma {}", scene.gamma)?;
+    writeln!(w, "tonemap {}", scene.tonemap.name())?;
+    let e = &scene.auto_exposure;
+    let mode = if e.enabled { "auto" } else { "manual" };
+    writeln!(w, "exposure {} {} {} {}", mode, e.compensation, e.min_ev, e.max_ev)?;
+    let b = &scene.bloom;
+    let mode = match b.mode {
+        BloomMode::On => "on",
+        BloomMode::Debug => "debug",
+        BloomMode::Off => "off",
+    };
+    writeln!(w, "bloom {} {} {} {}", mode, b.intensity, b.radius, b.threshold)?;
+    writeln!(w, "ssao {} {} {}", on_off(scene.ssao.enabled), scene.ssao.radius, scene.ssao.intensity)?;
+    writeln!(w, "ssr {} {}", on_off(scene.ssr.enabled), scene.ssr.intensity)?;
+    writeln!(w, "aa {}", scene.aa.name())?;
+
+    let g = &scene.grade;
+    if let Some(ref lut) = scene.lut {
+        writeln!(w, "lut {} {}", lut.display(), on_off(g.lut_enabled))?;
+    }
+    writeln!(w, "lift {} {} {}", g.lift[0], g.lift[1], g.lift[2])?;
+    writeln!(w, "grade_gamma {} {} {}", g.gamma[0], g.gamma[1], g.gamma[2])?;
+    writeln!(w, "gain {} {} {}", g.gain[0], g.gain[1], g.gain[2])?;
+    writeln!(w, "saturation {}", g.saturation)?;
+    writeln!(w, "white_balance {} {}", g.temperature, g.tint)?;
+
+    writeln!(w, "\n# lights")?;
+    rig::write_rig(&scene.rig, w)
+}
+
+pub fn save_scene<P: AsRef<Path>>(scene: &Scene, path: P) -> Result<(), String> {
+    let display = format!("{}", path.as_ref().display());
+
+    match File::create(path) {
+        Ok(mut f) => write_scene(scene, &mut f)
+            .map_err(|e| format!("File \"{}\" could not be written: {:?}", display, e)),
+        Err(e) => Err(format!("File \"{}\" could not be created: {:?}", display, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cgmath::{Point3, Deg, vec3};
+
+    use post::{ToneMap, BloomMode, AntiAlias};
+    use rig::{self, Rig, Light, LightKind, Motion, Shadow};
+    use shapes::Shape;
+    use super::*;
+
+    // Values are picked to survive the trip through text exactly, and the Debug output covers
+    // every field, so comparing it catches anything the writer drops or the parser misreads.
+    fn round_trip(scene: &Scene) {
+        let mut text = Vec::new();
+        write_scene(scene, &mut text).unwrap();
+        let loaded = load_scene(&text[..]).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", scene), "\n{}", String::from_utf8_lossy(&text));
+    }
+
+    fn color(hex: &str, intensity: f32) -> [f32; 4] {
+        let c = rig::parse_hex(hex).unwrap();
+        [c[0], c[1], c[2], intensity]
+    }
+
+    #[test]
+    fn default_round_trip() {
+        round_trip(&Scene::default());
+    }
+
+    #[test]
+    fn full_round_trip() {
+        let mut object = SceneObject::new(PathBuf::from("models/car"));
+        object.position = vec3(1., -2., 0.5);
+        object.rotation = vec3(0., 90., -45.);
+        object.scale = 2.;
+        object.normalize = Some(1.5);
+        object.shape = Some(Shape::Cube);
+        object.instances.push(SceneInstance { position: vec3(0., 0., 0.), rotation: vec3(0., 0., 0.), scale: 1. });
+        object.instances.push(SceneInstance { position: vec3(3., 0., -1.), rotation: vec3(10., 20., 30.), scale: 0.5 });
+        object.parts.push(ScenePart {
+            name: "front wheel".to_owned(),
+            position: vec3(0., 0.25, 0.),
+            rotation: vec3(0., 0., 180.),
+            scale: 1.25,
+        });
+
+        let ambient = color("4d479b", 0.25);
+        let light = |name: &str, kind, motion| Light {
+            name: name.to_owned(),
+            kind: kind,
+            color: color("e0bd91", 80.),
+            ambient: ambient,
+            motion: motion,
+            target: Point3::new(0., 0.5, 0.),
+            shadow: Shadow::default(),
+        };
+        let mut key = light("key", LightKind::Spot { inner: Deg(20.), outer: Deg(30.) }, Motion::Fixed(Point3::new(4., 5., 6.)));
+        key.shadow = Shadow { enabled: true, fov: Deg(45.), bias: 0.001, .. Shadow::default() };
+        let mut sweep = light("sweep", LightKind::Point,
+            Motion::Path(vec![(0., Point3::new(-6., 3., 0.)), (4., Point3::new(6., 3., 0.))]));
+        sweep.shadow.enabled = false;
+        let orbit = light("orbit", LightKind::Point, Motion::Orbit {
+            base_angle: Deg(120.),
+            speed: 30.,
+            wobble_speed: 13.,
+            wobble: 45.,
+            dist: 7.,
+            center: Point3::new(0., 1., 0.),
+        });
+
+        let mut scene = Scene {
+            objects: vec![object, SceneObject::new(PathBuf::from("models/plain"))],
+            board: Some(Board { size: 5, spacing: 1.25, albedo: rig::parse_hex("808080").unwrap() }),
+            ground: Some(-1.),
+            rig: Rig { lights: vec![key, sweep, orbit], shadow_size: 1024 },
+            camera: Some(CameraPose { origin: Point3::new(1., 2., 3.), theta: Deg(30.), phi: Deg(-15.), dist: 6. }),
+            gamma: 2.,
+            tonemap: ToneMap::Aces,
+            aa: AntiAlias::Taa,
+            lut: Some(PathBuf::from("luts/film.cube")),
+            .. Scene::default()
+        };
+        scene.lens.focal_length = 50.;
+        scene.lens.f_stop = 4.;
+        scene.lens.shutter = 0.125;
+        scene.lens.iso = 200.;
+        scene.dof.enabled = true;
+        scene.dof.focus_point = Some((0.25, 0.75));
+        scene.auto_exposure.compensation = 1.5; // written even while manual
+        scene.auto_exposure.min_ev = -4.;
+        scene.auto_exposure.max_ev = 12.;
+        scene.bloom.mode = BloomMode::Debug;
+        scene.bloom.intensity = 0.125;
+        scene.bloom.radius = 2.;
+        scene.bloom.threshold = 8.;
+        scene.bloom.knee = 4.;
+        scene.ssao.enabled = false;
+        scene.ssao.radius = 0.5;
+        scene.ssao.intensity = 2.;
+        scene.ssr.intensity = 0.5;
+        scene.grade.lift = [0.125, 0., -0.125];
+        scene.grade.gamma = [1., 1.5, 1.];
+        scene.grade.gain = [2., 1., 0.5];
+        scene.grade.saturation = 0.75;
+        scene.grade.temperature = -0.25;
+        scene.grade.tint = 0.125;
+        scene.grade.lut_enabled = false;
+
+        round_trip(&scene);
+    }
+}
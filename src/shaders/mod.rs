@@ -2,10 +2,13 @@
 
 #[macro_use]
 mod util;
+mod watch;
 
 use gfx;
 use self::util::file;
 
+pub use self::watch::Watcher;
+
 pub const LIGHT_COUNT: usize = 2;
 
 shader!(deferred {
@@ -13,13 +16,16 @@ shader!(deferred {
                 .define("VIEWPROJ")
                 .define("TAN")
                 .define("TEX")
-                .define("NORM"),
+                .define("NORM")
+                .define("INSTANCED")
+                .define("MATERIAL"),
             fragment: file("shaders/deferred.f.glsl")
         });
 
 shader!(shadow {
             vertex: file("shaders/transform.v.glsl")
-                .define("VIEWPROJ"),
+                .define("VIEWPROJ")
+                .define("INSTANCED"),
             fragment: file("shaders/shadow.f.glsl")
         });
 
@@ -28,7 +34,68 @@ shader!(pbr {
             fragment: file("shaders/pbr.f.glsl")
         });
 
+shader!(ssao {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/ssao.f.glsl")
+        });
+
+shader!(ssao_blur {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/ssao_blur.f.glsl")
+        });
+
+shader!(hiz {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/hiz.f.glsl")
+        });
+
+shader!(ssr {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/ssr.f.glsl")
+        });
+
+shader!(dof {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/dof.f.glsl")
+        });
+
+shader!(log_lum {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/log_lum.f.glsl")
+        });
+
+shader!(adapt {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/adapt.f.glsl")
+        });
+
+shader!(bloom_down {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/bloom_down.f.glsl")
+        });
+
+shader!(bloom_up {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/bloom_up.f.glsl")
+        });
+
 shader!(ldr {
             vertex: file("shaders/blit.v.glsl"),
             fragment: file("shaders/ldr.f.glsl")
+        });
+
+shader!(ldr_debug {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/ldr.f.glsl")
+                .define("DEBUG_VIEW")
+        });
+
+shader!(taa {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/taa.f.glsl")
+        });
+
+shader!(fxaa {
+            vertex: file("shaders/blit.v.glsl"),
+            fragment: file("shaders/fxaa.f.glsl")
         });
\ No newline at end of file
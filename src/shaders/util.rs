@@ -1,73 +1,202 @@
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
 
+use gfx::shade::core::CreateShaderError;
+
 macro_rules! shader {
-    ($name:ident { $($x:tt)+ }) => (pub fn $name<R: gfx::Resources, F: gfx::Factory<R>>(factory: &mut F) 
-        -> Result<gfx::ShaderSet<R>, gfx::shade::core::CreateShaderError> {
-        Ok(shader_set!(factory, $($x)+))
-    })
+    ($name:ident { $($x:tt)+ }) => (
+        pub fn $name<R: gfx::Resources, F: gfx::Factory<R>>(factory: &mut F) 
+            -> Result<gfx::ShaderSet<R>, gfx::shade::core::CreateShaderError> {
+            Ok(shader_set!(factory, $($x)+))
+        }
+
+        pub mod $name {
+            use std::path::PathBuf;
+            use super::util::file;
+
+            /// The files the shader reads, includes too.
+            pub fn files() -> Vec<PathBuf> {
+                shader_files!($($x)+)
+            }
+        }
+    )
+}
+
+macro_rules! shader_files {
+    ($($stage:ident: $s:expr),+ $(,)*) => ({
+        let mut files = Vec::new();
+        $(files.extend($s.files());)+
+        files
+    });
 }
 
 macro_rules! shader_set {
     ($f:ident, vertex: $v:expr, fragment: $p:expr $(,)*) => ({
-        let v = $v.build().into_bytes();
-        let p = $p.build().into_bytes();
+        let v = $v.build()?;
+        let p = $p.build()?;
         gfx::ShaderSet::Simple(
-            $f.create_shader_vertex(&v)?,
-            $f.create_shader_pixel(&p)?,
+            $f.create_shader_vertex(v.text.as_bytes()).map_err(|e| v.annotate(e))?,
+            $f.create_shader_pixel(p.text.as_bytes()).map_err(|e| p.annotate(e))?,
         )
     });
     ($f:ident, vertex: $v:expr, geometry: $g:expr, fragment: $p:expr $(,)*) => ({
-        let v = $v.build().into_bytes();
-        let g = $g.build().into_bytes();
-        let p = $p.build().into_bytes();
-        gfx::ShaderSet::Simple(
-            $f.create_shader_vertex(&v)?,
-            $f.create_shader_geometry(&v)?,
-            $f.create_shader_pixel(&p)?,
+        let v = $v.build()?;
+        let g = $g.build()?;
+        let p = $p.build()?;
+        gfx::ShaderSet::Geometry(
+            $f.create_shader_vertex(v.text.as_bytes()).map_err(|e| v.annotate(e))?,
+            $f.create_shader_geometry(g.text.as_bytes()).map_err(|e| g.annotate(e))?,
+            $f.create_shader_pixel(p.text.as_bytes()).map_err(|e| p.annotate(e))?,
         )
     });
     ($f:ident, vertex: $v:expr, tessellation_control: $h:expr, tessellation_evaluation: $d:expr, fragment: $p:expr $(,)*) => ({
-        let v = $v.build().into_bytes();
-        let h = $h.build().into_bytes();
-        let d = $d.build().into_bytes();
-        let p = $p.build().into_bytes();
+        let v = $v.build()?;
+        let h = $h.build()?;
+        let d = $d.build()?;
+        let p = $p.build()?;
         gfx::ShaderSet::Tessellated(
-            $f.create_shader_vertex(&v)?,
-            $f.create_shader_hull(&h)?,
-            $f.create_shader_domain(&d)?,
-            $f.create_shader_pixel(&p)?,
+            $f.create_shader_vertex(v.text.as_bytes()).map_err(|e| v.annotate(e))?,
+            $f.create_shader_hull(h.text.as_bytes()).map_err(|e| h.annotate(e))?,
+            $f.create_shader_domain(d.text.as_bytes()).map_err(|e| d.annotate(e))?,
+            $f.create_shader_pixel(p.text.as_bytes()).map_err(|e| p.annotate(e))?,
         )
     });
 }
 
-macro_rules! build_shader {
-    ($first:tt($(p:tt)*)$(.$x:tt)*) => (BuildShader::$first($(p)*)$(.$x)*.build().into_bytes())
-}
-
-
 pub struct BuildShader {
     prefix: String,
-    source: String,
-    name: Option<String>,
+    source: Result<String, String>, // the file, or why it couldn't be read
+    path: PathBuf,
 }
 
+/// Reads a shader file. A missing file fails when the shader is built, not here.
 pub fn file(fname: &str) -> BuildShader {
     let path = Path::new(fname);
-    let mut build = BuildShader {
+    let mut source = String::new();
+    let read = File::open(path).and_then(|mut f| f.read_to_string(&mut source));
+
+    BuildShader {
         prefix: String::new(),
-        source: String::new(),
-        name: match path.file_name() {
-            Some(v) => v.to_str().map(|v| v.to_owned()),
-            None => None,
+        source: match read {
+            Ok(_) => Ok(source),
+            Err(e) => Err(format!("Shader \"{}\" could not be read: {}", fname, e)),
         },
-    };
-    File::open(path)
-        .expect(&("Shader \"".to_owned() + fname + "\" not found"))
-        .read_to_string(&mut build.source)
-        .unwrap();
-    build
+        path: path.to_path_buf(),
+    }
+}
+
+/// A shader with its includes expanded. Each `#line` directive carries the index of a file in
+/// `files` as its source string number, so the compiler's log can name the file.
+pub struct ShaderSource {
+    pub text: String,
+    pub files: Vec<String>,
+}
+
+impl ShaderSource {
+    /// Replaces the source string numbers at the start of each log line with file names, for the
+    /// "0:12(5):" (Mesa), "ERROR: 0:12:" (AMD) and "0(12) :" (NVIDIA) styles.
+    pub fn annotate(&self, e: CreateShaderError) -> CreateShaderError {
+        let log = match e {
+            CreateShaderError::CompilationFailed(log) => log,
+            e => return e,
+        };
+
+        let lines: Vec<String> = log.lines().map(|line| {
+            let skip = ["ERROR: ", "WARNING: "].iter()
+                .find(|p| line.starts_with(*p))
+                .map(|p| p.len())
+                .unwrap_or(0);
+            let (head, rest) = line.split_at(skip);
+            let digits = rest.find(|c: char| !c.is_digit(10)).unwrap_or(rest.len());
+            let file = rest[..digits].parse::<usize>().ok().and_then(|id| self.files.get(id));
+
+            match (file, rest[digits..].chars().next()) {
+                (Some(file), Some(':')) | (Some(file), Some('(')) => format!("{}{}{}", head, file, &rest[digits..]),
+                _ => line.to_owned(),
+            }
+        }).collect();
+
+        CreateShaderError::CompilationFailed(lines.join("\n"))
+    }
+}
+
+/// The quoted path of an `#include "file"` line, `Some(Err)` if the line is a malformed include.
+fn include_target(line: &str) -> Option<Result<&str, ()>> {
+    let line = line.trim();
+    if !line.starts_with("#include") { return None }
+
+    let arg = line["#include".len()..].trim();
+    Some(if arg.len() >= 2 && arg.starts_with('"') && arg.ends_with('"') {
+        Ok(&arg[1..arg.len() - 1])
+    } else {
+        Err(())
+    })
+}
+
+/// Expands includes depth first. Every expansion sits inside an include guard, so it's the GLSL
+/// preprocessor that keeps only the first copy it compiles, and an include under an `#ifdef`
+/// that is off doesn't hide a later one. Including a file that is still being expanded is an
+/// error.
+struct Includer {
+    files: Vec<String>,
+    keys: Vec<PathBuf>, // canonical paths, by source string number
+    stack: Vec<PathBuf>,
+    text: String,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Includer {
+    fn expand(&mut self, path: &Path, source: &str, id: usize, first_line: usize) -> Result<(), String> {
+        self.stack.push(canonical(path));
+
+        for (i, line) in source.lines().enumerate() {
+            let target = match include_target(line) {
+                Some(Ok(t)) => t,
+                Some(Err(())) => return Err(format!("{}:{}: expected #include \"file\"", path.display(), first_line + i)),
+                None => {
+                    self.text += line;
+                    self.text.push('\n');
+                    continue;
+                },
+            };
+
+            let inc = path.parent().unwrap_or(Path::new("")).join(target);
+            let key = canonical(&inc);
+            if self.stack.contains(&key) {
+                let cycle: Vec<_> = self.stack.iter()
+                    .skip_while(|p| **p != key)
+                    .chain(Some(&key))
+                    .map(|p| p.display().to_string())
+                    .collect();
+                return Err(format!("{}:{}: include cycle {}", path.display(), first_line + i, cycle.join(" -> ")));
+            }
+
+            // listed before reading, so a missing include is still watched
+            let inc_id = match self.keys.iter().position(|k| *k == key) {
+                Some(id) => id,
+                None => {
+                    self.files.push(inc.display().to_string());
+                    self.keys.push(key);
+                    self.files.len() - 1
+                },
+            };
+
+            let mut inc_source = String::new();
+            File::open(&inc)
+                .and_then(|mut f| f.read_to_string(&mut inc_source))
+                .map_err(|e| format!("{}:{}: could not include \"{}\": {}", path.display(), first_line + i, inc.display(), e))?;
+            self.text += &format!("#ifndef INCLUDED_{0}\n#define INCLUDED_{0}\n#line 1 {0}\n", inc_id);
+            self.expand(&inc, &inc_source, inc_id, 1)?;
+            self.text += &format!("#endif\n#line {} {}\n", first_line + i + 1, id);
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
 }
 
 impl BuildShader {
@@ -95,12 +224,68 @@ impl BuildShader {
         self
     }
 
-    pub fn build(self) -> String {
-        if self.source.starts_with("#version") {
-            let (ver, src) = self.source.split_at(self.source.find('\n').unwrap_or(self.source.len()));
-            format!("{}\n{}#line 2\n{}", ver, self.prefix, src)
+    fn expand(&self) -> (Includer, Result<(), String>) {
+        let mut includer = Includer {
+            files: vec![self.path.display().to_string()],
+            keys: vec![canonical(&self.path)],
+            stack: Vec::new(),
+            text: String::new(),
+        };
+
+        let source = match self.source {
+            Ok(ref s) => s,
+            Err(ref e) => return (includer, Err(e.clone())),
+        };
+
+        let (version, src, first_line) = if source.starts_with("#version") {
+            let (ver, src) = source.split_at(source.find('\n').unwrap_or(source.len()));
+            (format!("{}\n", ver), src.get(1..).unwrap_or(""), 2)
         } else {
-            format!("{}#line 1\n{}", self.prefix, self.source)
-        }
+            (String::new(), &source[..], 1)
+        };
+
+        includer.text = format!("{}{}#line {} 0\n", version, self.prefix, first_line);
+        let result = includer.expand(&self.path, src, 0, first_line);
+        (includer, result)
+    }
+
+    /// Puts the defines after the `#version` line and expands `#include "file"` lines, relative
+    /// to the including file. Unreadable files and include cycles fail like a compile error.
+    pub fn build(self) -> Result<ShaderSource, CreateShaderError> {
+        let (includer, result) = self.expand();
+        result.map_err(CreateShaderError::CompilationFailed)?;
+
+        Ok(ShaderSource {
+            text: includer.text,
+            files: includer.files,
+        })
+    }
+
+    /// Every file the shader reads, as far as it gets when something is missing.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.expand().0.files.into_iter().map(PathBuf::from).collect()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::prelude::*;
+
+    use super::file;
+
+    #[test]
+    fn include_under_ifdef() {
+        let dir = env::temp_dir().join("pbr-include-test");
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("a.glsl")).unwrap().write_all(b"float a() { return 1.0; }\n").unwrap();
+        File::create(dir.join("main.glsl")).unwrap()
+            .write_all(b"#version 150 core\n#ifdef X\n#include \"a.glsl\"\n#endif\n#include \"a.glsl\"\n").unwrap();
+
+        // the second include must still be there when X is off, the guard drops it when X is on
+        let text = file(dir.join("main.glsl").to_str().unwrap()).build().ok().unwrap().text;
+        assert_eq!(text.matches("return 1.0").count(), 2);
+        assert_eq!(text.matches("#ifndef INCLUDED_1").count(), 2);
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls the files in one directory (not its subdirectories) for changes.
+pub struct Watcher {
+    dir: PathBuf,
+    times: HashMap<PathBuf, SystemTime>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return HashMap::new(),
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            match e.metadata().and_then(|m| m.modified()) {
+                Ok(time) => Some((path, time)),
+                Err(_) => None,
+            }
+        })
+        .collect()
+}
+
+impl Watcher {
+    /// Starts from the directory as it is now, looking again at most once per `interval`.
+    pub fn new<P: AsRef<Path>>(dir: P, interval: Duration) -> Watcher {
+        Watcher {
+            dir: dir.as_ref().to_path_buf(),
+            times: scan(dir.as_ref()),
+            interval: interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// The files that were changed, added or removed since the last poll. Empty until the
+    /// interval is up.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval { return Vec::new() }
+        self.last_poll = Instant::now();
+
+        let times = scan(&self.dir);
+        let mut changed: Vec<PathBuf> = times.iter()
+            .filter(|&(path, time)| self.times.get(path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(self.times.keys().filter(|p| !times.contains_key(*p)).cloned());
+
+        self.times = times;
+        changed
+    }
+}
@@ -0,0 +1,201 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3, vec3};
+
+use define::Vtnt;
+use graph::Aabb;
+
+//
+// Tangents follow the same convention as loaded models: the tangent points along +u and the
+// bitangent along +v, with v going up the texture as in OBJ files.
+//
+
+/// Built in meshes that can stand in for an object's model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Sphere,
+    Cube,
+    Plane,
+}
+
+pub const SHAPES: [Shape; 3] = [Shape::Sphere, Shape::Cube, Shape::Plane];
+
+impl Shape {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Shape::Sphere => "sphere",
+            Shape::Cube => "cube",
+            Shape::Plane => "plane",
+        }
+    }
+
+    /// The shape at unit size: a sphere of radius 1, a cube and a plane from -1 to 1.
+    pub fn mesh(&self) -> (Vec<Vtnt>, Vec<u32>) {
+        match *self {
+            Shape::Sphere => uv_sphere(1., 64, 32),
+            Shape::Cube => cube(1.),
+            Shape::Plane => plane(1.),
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shape, String> {
+        SHAPES.iter()
+            .cloned()
+            .find(|t| t.name() == s)
+            .ok_or_else(|| format!("Unknown shape \"{}\"", s))
+    }
+}
+
+/// The bounds of a generated mesh.
+pub fn bounds(verts: &[Vtnt]) -> Aabb {
+    let mut b = Aabb::new(Point3::from(verts[0].a_pos));
+    for v in &verts[1..] {
+        b.extend(Point3::from(v.a_pos));
+    }
+    b
+}
+
+/// A sphere with `segments` around the equator and `rings` from pole to pole. The seam and the
+/// poles repeat vertices so the uvs can wrap.
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> (Vec<Vtnt>, Vec<u32>) {
+    let mut verts = Vec::with_capacity((segments + 1) * (rings + 1));
+    for j in 0..rings + 1 {
+        let v = j as f32 / rings as f32;
+        let (sin_a, cos_a) = (v * PI).sin_cos(); // a is the angle from the south pole
+        for i in 0..segments + 1 {
+            let u = i as f32 / segments as f32;
+            let (sin_p, cos_p) = (u * 2. * PI).sin_cos();
+
+            let n = vec3(sin_a * cos_p, -cos_a, -sin_a * sin_p);
+            verts.push(Vtnt {
+                a_pos: (n * radius).into(),
+                a_tex: [u, v],
+                a_nor: n.into(),
+                a_tan: [-sin_p, 0., -cos_p], // still defined at the poles
+                a_btn: [cos_a * cos_p, sin_a, -cos_a * sin_p],
+            });
+        }
+    }
+
+    let row = segments as u32 + 1;
+    let mut indices = Vec::with_capacity(segments * rings * 6);
+    for j in 0..rings as u32 {
+        for i in 0..segments as u32 {
+            let (a, b) = (j * row + i, j * row + i + 1);
+            let (c, d) = (a + row, b + row);
+            indices.extend_from_slice(&[a, b, d, a, d, c]);
+        }
+    }
+
+    (verts, indices)
+}
+
+/// Adds a square facing `n`, with the texture's u along `t` and v along `b`.
+fn face(verts: &mut Vec<Vtnt>, indices: &mut Vec<u32>, center: Vector3<f32>, n: Vector3<f32>,
+        t: Vector3<f32>, b: Vector3<f32>, half: f32)
+{
+    let base = verts.len() as u32;
+    for &(s, r) in &[(-1f32, -1f32), (1., -1.), (-1., 1.), (1., 1.)] {
+        verts.push(Vtnt {
+            a_pos: (center + (t * s + b * r) * half).into(),
+            a_tex: [(s + 1.) * 0.5, (r + 1.) * 0.5],
+            a_nor: n.into(),
+            a_tan: t.into(),
+            a_btn: b.into(),
+        });
+    }
+    indices.extend_from_slice(&[base, base + 1, base + 3, base, base + 3, base + 2]);
+}
+
+/// A cube from `-half` to `half`, with the whole texture on every face.
+pub fn cube(half: f32) -> (Vec<Vtnt>, Vec<u32>) {
+    let (mut verts, mut indices) = (Vec::with_capacity(24), Vec::with_capacity(36));
+
+    // normal, tangent and bitangent of each face, right handed
+    let faces = [
+        (vec3( 1., 0., 0.), vec3(0., 0., -1.), vec3(0., 1., 0.)),
+        (vec3(-1., 0., 0.), vec3(0., 0., 1.), vec3(0., 1., 0.)),
+        (vec3(0.,  1., 0.), vec3(1., 0., 0.), vec3(0., 0., -1.)),
+        (vec3(0., -1., 0.), vec3(1., 0., 0.), vec3(0., 0., 1.)),
+        (vec3(0., 0.,  1.), vec3(1., 0., 0.), vec3(0., 1., 0.)),
+        (vec3(0., 0., -1.), vec3(-1., 0., 0.), vec3(0., 1., 0.)),
+    ];
+    for &(n, t, b) in &faces {
+        face(&mut verts, &mut indices, n * half, n, t, b, half);
+    }
+
+    (verts, indices)
+}
+
+/// A square on the x/z plane from `-half` to `half`, facing up.
+pub fn plane(half: f32) -> (Vec<Vtnt>, Vec<u32>) {
+    let (mut verts, mut indices) = (Vec::with_capacity(4), Vec::with_capacity(6));
+    face(&mut verts, &mut indices, Vector3::zero(), vec3(0., 1., 0.), vec3(1., 0., 0.), vec3(0., 0., -1.), half);
+    (verts, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::prelude::*;
+    use cgmath::Vector3;
+
+    use define::Vtnt;
+    use super::*;
+
+    // Every vertex frame has to be unit length and right handed (normal = tangent x bitangent),
+    // with the tangent following +u and the bitangent +v across each triangle it is part of, and
+    // every triangle has to wind counter-clockwise seen from the front.
+    fn check_frames(name: &str, (verts, indices): (Vec<Vtnt>, Vec<u32>)) {
+        for v in &verts {
+            let (n, t, b) = (Vector3::from(v.a_nor), Vector3::from(v.a_tan), Vector3::from(v.a_btn));
+            for &(what, len) in &[("normal", n.magnitude()), ("tangent", t.magnitude()), ("bitangent", b.magnitude())] {
+                assert!((len - 1.).abs() < 1e-4, "{} {} isn't unit length in {:?}", name, what, v);
+            }
+            assert!(t.cross(b).dot(n) > 0.99, "{} frame isn't right handed: {:?}", name, v);
+        }
+
+        for tri in indices.chunks(3) {
+            let v: Vec<&Vtnt> = tri.iter().map(|&i| &verts[i as usize]).collect();
+            let e1 = Vector3::from(v[1].a_pos) - Vector3::from(v[0].a_pos);
+            let e2 = Vector3::from(v[2].a_pos) - Vector3::from(v[0].a_pos);
+            let (du1, dv1) = (v[1].a_tex[0] - v[0].a_tex[0], v[1].a_tex[1] - v[0].a_tex[1]);
+            let (du2, dv2) = (v[2].a_tex[0] - v[0].a_tex[0], v[2].a_tex[1] - v[0].a_tex[1]);
+
+            // collapsed triangles at the sphere's poles have no direction to follow
+            if e1.cross(e2).magnitude() < 1e-6 { continue }
+            assert!(e1.cross(e2).dot(Vector3::from(v[0].a_nor)) > 0., "{} triangle {:?} isn't counter-clockwise", name, tri);
+
+            let r = 1. / (du1 * dv2 - du2 * dv1);
+            let dp_du = (e1 * dv2 - e2 * dv1) * r;
+            let dp_dv = (e2 * du1 - e1 * du2) * r;
+            for v in &v {
+                assert!(Vector3::from(v.a_tan).dot(dp_du) > 0., "{} tangent doesn't follow u at {:?}", name, v);
+                assert!(Vector3::from(v.a_btn).dot(dp_dv) > 0., "{} bitangent doesn't follow v at {:?}", name, v);
+            }
+        }
+    }
+
+    #[test]
+    fn tangent_frames() {
+        check_frames("sphere", uv_sphere(1., 16, 8));
+        check_frames("cube", cube(1.));
+        check_frames("plane", plane(1.));
+    }
+
+    #[test]
+    fn outward_normals() {
+        for shape in &SHAPES {
+            let (verts, _) = shape.mesh();
+            for v in &verts {
+                let (p, n) = (Vector3::from(v.a_pos), Vector3::from(v.a_nor));
+                assert!(p.dot(n) >= 0., "{} normal points inwards at {:?}", shape.name(), v);
+            }
+        }
+    }
+}
@@ -4,16 +4,28 @@ use std::path::Path;
 use std::fs::File;
  
 use define::{V, Vn, Vt, Vtn, Vtnt, VertexSlice};
+use graph::Aabb;
 use gfx;
 
 use cgmath::prelude::*;
 use cgmath::{Point2, Point3,Vector3};
 
+/// The faces between two `o` or `g` lines, recentered on the middle of their bounds.
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub name: String,
+    pub start: u32, // range in the index buffer
+    pub end: u32,
+    pub origin: Vector3<f32>, // where the group was before recentering
+    pub bounds: Aabb,
+}
+
 
 #[derive(Clone, Debug)] 
 pub struct WavefrontMesh<V: WavefrontVertex> { 
     pub indicies: Vec<u32>, 
     pub verts: Vec<V>, 
+    pub groups: Vec<Group>,
 }
 
 impl<V: WavefrontVertex> WavefrontMesh<V>
@@ -31,6 +43,8 @@ pub trait WavefrontVertex {
 
     fn requires_edges() -> bool;
 
+    fn position(&mut self) -> &mut [f32; 3];
+
     fn acc_tri(a: &mut Self, b: &mut Self, c: &mut Self);
 } 
  
@@ -40,6 +54,7 @@ impl WavefrontVertex for V {
     }
 
     fn requires_edges() -> bool { false }
+    fn position(&mut self) -> &mut [f32; 3] { &mut self.a_pos }
     fn acc_tri(_: &mut Self, _: &mut Self, _: &mut Self) {
         unreachable!();
     }
@@ -54,6 +69,7 @@ impl WavefrontVertex for Vn {
     }
 
     fn requires_edges() -> bool { false }
+    fn position(&mut self) -> &mut [f32; 3] { &mut self.a_pos }
     fn acc_tri(_: &mut Self, _: &mut Self, _: &mut Self) {
         unreachable!();
     }
@@ -68,6 +84,7 @@ impl WavefrontVertex for Vt {
     }
 
     fn requires_edges() -> bool { false }
+    fn position(&mut self) -> &mut [f32; 3] { &mut self.a_pos }
     fn acc_tri(_: &mut Self, _: &mut Self, _: &mut Self) {
         unreachable!();
     }
@@ -83,6 +100,7 @@ impl WavefrontVertex for Vtn {
     }
 
     fn requires_edges() -> bool { false }
+    fn position(&mut self) -> &mut [f32; 3] { &mut self.a_pos }
     fn acc_tri(_: &mut Self, _: &mut Self, _: &mut Self) {
         unreachable!();
     }
@@ -111,6 +129,7 @@ impl WavefrontVertex for Vtnt {
     }
 
     fn requires_edges() -> bool { true }
+    fn position(&mut self) -> &mut [f32; 3] { &mut self.a_pos }
 
     fn acc_tri(a: &mut Self, b: &mut Self, c: &mut Self) {
         // positions
@@ -217,6 +236,40 @@ pub fn parse_inds(data: &str, line: usize) -> Result<IInds, usize> {
     Ok((pos, tex, norm)) 
 }
 
+fn finish_group<V: WavefrontVertex>(name: &str,
+                                    start: usize,
+                                    first_vert: usize,
+                                    inds: &[u32],
+                                    verts: &mut [V],
+                                    groups: &mut Vec<Group>) {
+    if inds.len() == start { return } // no faces
+
+    // every group has its own vertices, so they can be moved without affecting the others
+    let mut bounds = Aabb::new(Point3::from(*verts[first_vert].position()));
+    for v in &mut verts[first_vert..] {
+        bounds.extend(Point3::from(*v.position()));
+    }
+
+    let origin = bounds.center().to_vec();
+    for v in &mut verts[first_vert..] {
+        let p = v.position();
+        p[0] -= origin.x;
+        p[1] -= origin.y;
+        p[2] -= origin.z;
+    }
+
+    groups.push(Group {
+        name: name.to_string(),
+        start: start as u32,
+        end: inds.len() as u32,
+        origin: origin,
+        bounds: Aabb {
+            min: bounds.min + -origin,
+            max: bounds.max + -origin,
+        },
+    });
+}
+
 pub fn load_obj<V: WavefrontVertex, R: BufRead>(read: R) -> Result<WavefrontMesh<V>, usize> {
     let mut pos: Vec<[f32; 3]> = Vec::new();
     let mut nor = Vec::new();
@@ -227,6 +280,11 @@ pub fn load_obj<V: WavefrontVertex, R: BufRead>(read: R) -> Result<WavefrontMesh
 
     let mut inds = Vec::new();
 
+    let mut groups = Vec::new();
+    let mut group = String::from("default");
+    let mut group_start = 0;
+    let mut group_vert = 0;
+
     let mut linen = 0usize;
 
     for line in read.lines() {
@@ -290,20 +348,30 @@ pub fn load_obj<V: WavefrontVertex, R: BufRead>(read: R) -> Result<WavefrontMesh
 
                 inds.append(&mut triv);
             },
+            Some("o") | Some("g") => {
+                finish_group(&group, group_start, group_vert, &inds, &mut verts, &mut groups);
+                group = line.collect::<Vec<_>>().join(" ");
+                group_start = inds.len();
+                group_vert = verts.len();
+                dedup.clear();
+            },
             Some(_) => (),
             None => (),
         }
     }
 
+    finish_group(&group, group_start, group_vert, &inds, &mut verts, &mut groups);
+
     Ok(WavefrontMesh {
         verts: verts,
         indicies: inds,
-
+        groups: groups,
     })
 }
 
+/// Uploads the model, and returns the parsed mesh too for anything that needs it on the CPU.
 pub fn open_obj<V: WavefrontVertex, F, R, P: AsRef<Path>>(path: P, factory: &mut F) 
--> Result<VertexSlice<R, V>, String>
+-> Result<(VertexSlice<R, V>, WavefrontMesh<V>), String>
     where V: gfx::traits::Pod + gfx::pso::buffer::Structure<gfx::format::Format>,
     R: gfx::Resources, 
     F: gfx::traits::FactoryExt<R>
@@ -312,7 +380,7 @@ pub fn open_obj<V: WavefrontVertex, F, R, P: AsRef<Path>>(path: P, factory: &mut
 
     match File::open(path) {
         Ok(f) => match load_obj(BufReader::new(f)) {
-            Ok(obj) => Ok(obj.create_buffer(factory)),
+            Ok(obj) => Ok((obj.create_buffer(factory), obj)),
             Err(line) => Err(format!("Error parsing \"{}\" on line #{}", display, line)),
         },
         Err(e) => Err(format!("File \"{}\" could not be opened: {:?}", display, e)),